@@ -0,0 +1,208 @@
+use crate::history::History;
+use crate::*;
+use std::mem;
+
+/// Everything that belongs to one open file. The buffer being edited lives in the fields of
+/// `Output` itself; its slot in `Output::buffers` holds whatever was swapped out of them
+pub struct Buffer {
+    pub editor_rows: EditorRows,
+    pub cursor_controller: CursorController,
+    pub dirty: u64,
+    pub search_index: SearchIndex,
+    pub syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+    pub history: History,
+}
+
+impl Buffer {
+    pub fn new(win_size: (usize, usize)) -> Self {
+        Self {
+            editor_rows: EditorRows::new(),
+            cursor_controller: CursorController::new(win_size),
+            dirty: 0,
+            search_index: SearchIndex::new(),
+            syntax_highlight: None,
+            history: History::new(),
+        }
+    }
+
+    fn with_rows(
+        editor_rows: EditorRows,
+        syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+        win_size: (usize, usize),
+    ) -> Self {
+        Self {
+            editor_rows,
+            syntax_highlight,
+            ..Self::new(win_size)
+        }
+    }
+
+    /// Loads `file` into a new buffer, along with any note `EditorRows::from_file` has about
+    /// it. `syntax_highlight` overrides the highlighter picked from the extension
+    pub fn open(
+        file: PathBuf,
+        mut syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+        win_size: (usize, usize),
+    ) -> io::Result<(Self, Option<String>)> {
+        let (editor_rows, notice) = EditorRows::from_file(file, &mut syntax_highlight)?;
+        Ok((
+            Self::with_rows(editor_rows, syntax_highlight, win_size),
+            notice,
+        ))
+    }
+
+    pub fn from_bytes(
+        bytes: &[u8],
+        source: &str,
+        mut syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+        win_size: (usize, usize),
+    ) -> (Self, Option<String>) {
+        let (editor_rows, notice) =
+            EditorRows::from_bytes(bytes, source, None, &mut syntax_highlight);
+        (
+            Self::with_rows(editor_rows, syntax_highlight, win_size),
+            notice,
+        )
+    }
+}
+
+impl Output {
+    /// Exchanges the buffer being edited with the one stored at `index`
+    pub fn swap_buffer(&mut self, index: usize) {
+        let buffer = &mut self.buffers[index];
+        mem::swap(&mut self.editor_rows, &mut buffer.editor_rows);
+        mem::swap(&mut self.cursor_controller, &mut buffer.cursor_controller);
+        mem::swap(&mut self.dirty, &mut buffer.dirty);
+        mem::swap(&mut self.search_index, &mut buffer.search_index);
+        mem::swap(&mut self.syntax_highlight, &mut buffer.syntax_highlight);
+        mem::swap(&mut self.history, &mut buffer.history);
+    }
+
+    /// Makes `index` the buffer being edited and shows it in the focused window
+    pub fn activate_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        let (screen_columns, screen_rows) = (
+            self.cursor_controller.screen_columns,
+            self.cursor_controller.screen_rows,
+        );
+        self.swap_buffer(self.current_buffer);
+        self.swap_buffer(index);
+        self.current_buffer = index;
+        self.windows[self.current_window].buffer = index;
+        self.cursor_controller.screen_columns = screen_columns;
+        self.cursor_controller.screen_rows = screen_rows;
+    }
+
+    pub fn switch_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        self.activate_buffer(index);
+        self.status_message.set_message(format!(
+            "Buffer {}/{}: {}",
+            index + 1,
+            self.buffers.len(),
+            self.editor_rows.display_name()
+        ));
+    }
+
+    pub fn cycle_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len();
+        if count == 1 {
+            self.status_message
+                .set_message("No other buffers open".into());
+            return;
+        }
+        let index = if forward {
+            (self.current_buffer + 1) % count
+        } else {
+            (self.current_buffer + count - 1) % count
+        };
+        self.switch_buffer(index)
+    }
+
+    /// Name and modified state of every buffer, in order
+    pub fn buffer_list(&self) -> Vec<(&str, bool)> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                if index == self.current_buffer {
+                    (self.editor_rows.display_name(), self.dirty > 0)
+                } else {
+                    (buffer.editor_rows.display_name(), buffer.dirty > 0)
+                }
+            })
+            .collect()
+    }
+
+    pub fn open_buffer(&mut self, file: PathBuf) {
+        let existing = (0..self.buffers.len()).find(|index| {
+            let editor_rows = if *index == self.current_buffer {
+                &self.editor_rows
+            } else {
+                &self.buffers[*index].editor_rows
+            };
+            editor_rows.filename.as_ref() == Some(&file)
+        });
+        match existing {
+            Some(index) => self.switch_buffer(index),
+            None => match Buffer::open(file.clone(), None, self.win_size) {
+                Ok((mut buffer, notice)) => {
+                    buffer
+                        .editor_rows
+                        .configure(&self.config, buffer.syntax_highlight.as_deref());
+                    self.buffers.push(buffer);
+                    self.switch_buffer(self.buffers.len() - 1);
+                    if let Some(notice) = notice {
+                        self.status_message.set_message(notice)
+                    }
+                }
+                Err(err) => self.status_message.set_message(format!(
+                    "Can't open {}: {}",
+                    file.display(),
+                    err
+                )),
+            },
+        }
+    }
+
+    pub fn open_file(&mut self) -> io::Result<()> {
+        if let Some(file) = prompt!(
+            self,
+            "Open file: {} (ESC to cancel)",
+            history = file_history,
+            complete = complete_path
+        ) {
+            self.open_buffer(PathBuf::from(file))
+        }
+        Ok(())
+    }
+
+    pub fn list_buffers(&mut self) {
+        let list = self
+            .buffer_list()
+            .iter()
+            .enumerate()
+            .map(|(index, (name, dirty))| {
+                let marker = if index == self.current_buffer {
+                    ">"
+                } else {
+                    ""
+                };
+                format!(
+                    "{}{}:{}{}",
+                    marker,
+                    index + 1,
+                    name,
+                    if *dirty { "*" } else { "" }
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        self.status_message
+            .set_message(format!("Buffers: {}", list));
+    }
+}
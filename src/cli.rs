@@ -0,0 +1,219 @@
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+pub const USAGE: &str = "\
+Usage: pound [OPTIONS] [+LINE] [FILE[:LINE[:COL]]]...
+
+Options:
+  -R, --readonly       open every buffer read-only
+  -P, --pager          read-only with less-style keys: Space, b and q
+  -s, --syntax NAME    highlight with NAME instead of guessing from the file name
+  -h, --help           print this help and exit
+  -V, --version        print the version and exit
+
++LINE puts the cursor on LINE in the file that follows it. A FILE of - reads
+the buffer from standard input, with keys still read from the terminal. Run with
+no FILE and piped input, pound pages through that input.";
+
+pub enum Source {
+    Path(PathBuf),
+    Stdin,
+}
+
+/// A buffer to open and where to put the cursor in it, in any form `parse_location` takes
+pub struct FileArg {
+    pub source: Source,
+    pub location: Option<String>,
+}
+
+pub struct Args {
+    pub files: Vec<FileArg>,
+    pub read_only: bool,
+    pub pager: bool,
+    pub syntax: Option<String>,
+}
+
+impl Args {
+    pub fn reads_stdin(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| matches!(file.source, Source::Stdin))
+    }
+}
+
+pub enum Command {
+    Edit(Args),
+    Help,
+    Version,
+}
+
+static LOCATION: OnceLock<Regex> = OnceLock::new();
+
+/// Splits `path:line[:col]` as printed by compilers, unless a file has that exact name
+fn split_location(arg: &str) -> (PathBuf, Option<String>) {
+    let location = LOCATION.get_or_init(|| Regex::new(r"^(.+?):(\d+(?::\d+)?):?$").unwrap());
+    match location.captures(arg) {
+        Some(captures) if !Path::new(arg).exists() => {
+            (PathBuf::from(&captures[1]), Some(captures[2].to_string()))
+        }
+        _ => (PathBuf::from(arg), None),
+    }
+}
+
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args {
+        files: Vec::new(),
+        read_only: false,
+        pager: false,
+        syntax: None,
+    };
+    let mut location = None;
+    let mut options_ended = false;
+    while let Some(arg) = args.next() {
+        if !options_ended && arg.starts_with('-') && arg != "-" {
+            match arg.as_str() {
+                "--" => options_ended = true,
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "-R" | "--readonly" => parsed.read_only = true,
+                "-P" | "--pager" => parsed.pager = true,
+                "-s" | "--syntax" => {
+                    let name = args.next().ok_or(format!("{} needs a syntax name", arg))?;
+                    parsed.syntax = Some(name)
+                }
+                _ => match arg.strip_prefix("--syntax=") {
+                    Some(name) => parsed.syntax = Some(name.to_string()),
+                    None => return Err(format!("unknown option {}", arg)),
+                },
+            }
+            continue;
+        }
+        if let Some(line) = arg.strip_prefix('+').filter(|_| !options_ended) {
+            if line.is_empty() || !line.chars().all(|c| c.is_ascii_digit()) {
+                return Err(format!("invalid line number {}", arg));
+            }
+            location = Some(line.to_string());
+            continue;
+        }
+        let file = if arg == "-" {
+            FileArg {
+                source: Source::Stdin,
+                location: location.take(),
+            }
+        } else {
+            let (path, file_location) = split_location(&arg);
+            FileArg {
+                source: Source::Path(path),
+                location: file_location.or_else(|| location.take()),
+            }
+        };
+        parsed.files.push(file);
+    }
+    if location.is_some() {
+        return Err("+LINE must be followed by a file".into());
+    }
+    if parsed
+        .files
+        .iter()
+        .filter(|file| matches!(file.source, Source::Stdin))
+        .count()
+        > 1
+    {
+        return Err("standard input can only be read once".into());
+    }
+    Ok(Command::Edit(parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(args: &[&str]) -> Result<Args, String> {
+        match parse(args.iter().map(|arg| arg.to_string()))? {
+            Command::Edit(parsed) => Ok(parsed),
+            _ => panic!("expected files to edit"),
+        }
+    }
+
+    fn path(file: &FileArg) -> &Path {
+        match &file.source {
+            Source::Path(path) => path,
+            Source::Stdin => panic!("expected a path"),
+        }
+    }
+
+    #[test]
+    fn splits_compiler_locations() {
+        assert_eq!(
+            split_location("no/such/main.rs:12:5"),
+            (PathBuf::from("no/such/main.rs"), Some("12:5".into()))
+        );
+        assert_eq!(
+            split_location("no/such/main.rs:12:"),
+            (PathBuf::from("no/such/main.rs"), Some("12".into()))
+        );
+        assert_eq!(
+            split_location("no/such/main.rs:x"),
+            (PathBuf::from("no/such/main.rs:x"), None)
+        );
+    }
+
+    #[test]
+    fn keeps_a_file_named_like_a_location() {
+        let file = std::env::temp_dir().join(format!("pound-cli-{}:3", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let name = file.to_str().unwrap();
+        let split = split_location(name);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(split, (file.clone(), None));
+    }
+
+    #[test]
+    fn applies_line_numbers_to_the_next_file() {
+        let parsed = edit(&["+7", "a.txt", "b.txt:3:2", "-"]).unwrap();
+        assert_eq!(parsed.files.len(), 3);
+        assert_eq!(path(&parsed.files[0]), Path::new("a.txt"));
+        assert_eq!(parsed.files[0].location.as_deref(), Some("7"));
+        assert_eq!(path(&parsed.files[1]), Path::new("b.txt"));
+        assert_eq!(parsed.files[1].location.as_deref(), Some("3:2"));
+        assert!(matches!(parsed.files[2].source, Source::Stdin));
+        assert_eq!(parsed.files[2].location, None);
+    }
+
+    #[test]
+    fn reads_flags() {
+        let parsed = edit(&["-R", "--pager", "-s", "rust", "--", "-R"]).unwrap();
+        assert!(parsed.read_only && parsed.pager);
+        assert_eq!(parsed.syntax.as_deref(), Some("rust"));
+        assert_eq!(path(&parsed.files[0]), Path::new("-R"));
+        assert_eq!(edit(&["--syntax=c"]).unwrap().syntax.as_deref(), Some("c"));
+        assert!(matches!(
+            parse(["-h".to_string()].into_iter()),
+            Ok(Command::Help)
+        ));
+        assert!(matches!(
+            parse(["a".to_string(), "--version".to_string()].into_iter()),
+            Ok(Command::Version)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(edit(&["--bogus"]).err().unwrap(), "unknown option --bogus");
+        assert_eq!(edit(&["-s"]).err().unwrap(), "-s needs a syntax name");
+        assert_eq!(
+            edit(&["+1x", "a"]).err().unwrap(),
+            "invalid line number +1x"
+        );
+        assert_eq!(edit(&["+"]).err().unwrap(), "invalid line number +");
+        assert_eq!(
+            edit(&["a", "+3"]).err().unwrap(),
+            "+LINE must be followed by a file"
+        );
+        assert_eq!(
+            edit(&["-", "-"]).err().unwrap(),
+            "standard input can only be read once"
+        );
+    }
+}
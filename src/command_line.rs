@@ -0,0 +1,327 @@
+use crate::*;
+
+/// Commands that only make sense typed out, on top of every keymap command name
+const EX_COMMANDS: &[&str] = &[
+    "write", "quit", "quit!", "wq", "edit", "set", "syntax", "theme",
+];
+
+const OPTIONS: &[&str] = &[
+    "tabstop=",
+    "shiftwidth=",
+    "expandtab",
+    "noexpandtab",
+    "number",
+    "relativenumber",
+    "nonumber",
+    "readonly",
+    "noreadonly",
+    "fileformat=",
+    "filetype=",
+];
+
+/// The vi abbreviations for the commands above
+fn ex_alias(name: &str) -> &str {
+    match name {
+        "w" => "write",
+        "q" => "quit",
+        "q!" => "quit!",
+        "x" => "wq",
+        "e" => "edit",
+        name => name,
+    }
+}
+
+/// Scores how well `name` matches `pattern`, whose characters must all appear in it in
+/// order. Runs of adjacent characters and ones starting a word score higher
+fn fuzzy_score(pattern: &str, name: &str) -> Option<usize> {
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut score, mut from, mut previous) = (0, 0, None);
+    for c in pattern.chars() {
+        let index = (from..name.len()).find(|index| name[*index].eq_ignore_ascii_case(&c))?;
+        score += 1;
+        if index > 0 && previous == Some(index - 1) {
+            score += 4
+        }
+        if index == 0 || name[index - 1] == '-' {
+            score += 2
+        }
+        previous = Some(index);
+        from = index + 1;
+    }
+    Some(score)
+}
+
+/// Command names matching `pattern`, best first
+fn matching_names(pattern: &str) -> Vec<&'static str> {
+    let mut matches = Command::all()
+        .iter()
+        .map(|command| command.name())
+        .chain(EX_COMMANDS.iter().copied())
+        .filter_map(|name| Some((fuzzy_score(pattern, name)?, name)))
+        .collect::<Vec<(usize, &str)>>();
+    matches.sort_by(|(score, name), (other_score, other_name)| {
+        other_score
+            .cmp(score)
+            .then(name.len().cmp(&other_name.len()))
+            .then(name.cmp(other_name))
+    });
+    /* `quit` is both a command and an ex command */
+    matches.dedup();
+    matches.into_iter().map(|(_, name)| name).collect()
+}
+
+fn complete(output: &Output, input: &str) -> Vec<String> {
+    let (name, argument) = match input.split_once(' ') {
+        Some(split) => split,
+        None => {
+            return matching_names(input)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        }
+    };
+    /* only the last word of the argument is completed, so `set et ts=` works */
+    let (done, word) = match argument.rsplit_once(' ') {
+        Some((done, word)) => (format!("{} {} ", name, done), word),
+        None => (format!("{} ", name), argument),
+    };
+    let candidates = match ex_alias(name) {
+        "write" | "edit" => complete_path(output, word),
+        "set" => match word.split_once('=') {
+            Some((option @ ("filetype" | "ft"), name)) => complete_syntax(output, name)
+                .into_iter()
+                .map(|name| format!("{}={}", option, name))
+                .collect(),
+            _ => OPTIONS
+                .iter()
+                .filter(|option| option.starts_with(word))
+                .map(|option| option.to_string())
+                .collect(),
+        },
+        "syntax" => complete_syntax(output, word),
+        "theme" => Theme::names()
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .collect(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{}{}", done, candidate))
+        .collect()
+}
+
+impl Output {
+    /// Applies vi style options such as `tabstop=4 expandtab`; with none, shows the
+    /// current ones
+    fn set_options(&mut self, options: &str) {
+        let mut indentation = self.editor_rows.indentation;
+        if options.is_empty() {
+            let message = format!(
+                "tabstop={} shiftwidth={} {}expandtab fileformat={} filetype={}",
+                indentation.tab_width,
+                indentation.indent_width,
+                if indentation.style == IndentStyle::Spaces {
+                    ""
+                } else {
+                    "no"
+                },
+                match self.editor_rows.format.line_ending {
+                    LineEnding::Lf => "unix",
+                    LineEnding::CrLf => "dos",
+                },
+                self.syntax_highlight
+                    .as_ref()
+                    .map_or("none", |it| it.file_type())
+            );
+            self.status_message.set_message(message);
+            return;
+        }
+        for option in options.split_whitespace() {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (option, None),
+            };
+            let number = value
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|number| *number > 0);
+            match (name, value) {
+                ("tabstop" | "ts", Some(_)) if number.is_some() => {
+                    indentation.tab_width = number.unwrap()
+                }
+                ("shiftwidth" | "sw", Some(_)) if number.is_some() => {
+                    indentation.indent_width = number.unwrap()
+                }
+                ("expandtab" | "et", None) => indentation.style = IndentStyle::Spaces,
+                ("noexpandtab" | "noet", None) => indentation.style = IndentStyle::Tabs,
+                ("number" | "nu", None) => self.line_numbers = LineNumbers::Absolute,
+                ("relativenumber" | "rnu", None) => self.line_numbers = LineNumbers::Relative,
+                ("nonumber" | "nonu" | "norelativenumber" | "nornu", None) => {
+                    self.line_numbers = LineNumbers::Off
+                }
+                ("readonly" | "ro", None) => self.editor_rows.read_only = true,
+                ("noreadonly" | "noro", None) => self.editor_rows.read_only = false,
+                ("fileformat" | "ff", Some("unix")) => self.set_line_ending(LineEnding::Lf),
+                ("fileformat" | "ff", Some("dos")) => self.set_line_ending(LineEnding::CrLf),
+                /* the new file type brings its own indentation, which later options may change */
+                ("filetype" | "ft", Some(name))
+                    if ["none", "auto"].contains(&name)
+                        || Output::syntax_by_name(name).is_some() =>
+                {
+                    self.set_syntax_by_name(name);
+                    indentation = self.editor_rows.indentation
+                }
+                _ => {
+                    self.status_message
+                        .set_message(format!("Invalid option: {}", option));
+                    return;
+                }
+            }
+        }
+        self.editor_rows
+            .set_indentation(indentation, self.syntax_highlight.as_deref());
+        self.status_message.set_message(format!("set {}", options));
+    }
+
+    /// Switches to the theme `name`; with none, shows the one in use
+    fn set_theme(&mut self, name: &str) {
+        if name.is_empty() {
+            let theme = &self.config.theme;
+            let message = format!("theme {} ({})", theme.name, theme.depth.name());
+            self.status_message.set_message(message);
+            return;
+        }
+        match Theme::load(name) {
+            Ok(theme) => {
+                self.config.use_theme(theme);
+                self.status_message.set_message(format!("theme {}", name))
+            }
+            Err(problem) => self
+                .status_message
+                .set_message(format!("Can't use theme {}: {}", name, problem)),
+        }
+    }
+}
+
+impl Editor {
+    /// Reads a command from the `:` prompt and runs it, returning whether the editor should
+    /// keep going
+    pub fn command_line(&mut self) -> crossterm::Result<bool> {
+        match prompt!(
+            &mut self.output,
+            ":{}",
+            history = command_history,
+            complete = complete
+        ) {
+            Some(line) => self.run_command_line(line.trim()),
+            None => Ok(true),
+        }
+    }
+
+    /// Whether `:q` may exit. Unlike the Quit key it doesn't count down, refusing outright
+    /// while buffers are unsaved
+    fn quit_saved(&mut self) -> bool {
+        let unsaved = self.unsaved();
+        if !unsaved.is_empty() {
+            self.output.status_message.set_message(format!(
+                "No write since last change in {} (add ! to override)",
+                unsaved
+            ));
+        }
+        unsaved.is_empty()
+    }
+
+    fn run_command_line(&mut self, line: &str) -> crossterm::Result<bool> {
+        let output = &mut self.output;
+        let number_of_rows = output.editor_rows.number_of_rows();
+        if let Some(at) = parse_location(line, output.cursor_controller.cursor_y, number_of_rows) {
+            output.history.seal();
+            output.cursor_controller.go_to(at, &output.editor_rows);
+            return Ok(true);
+        }
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        /* fuzzy matches are only offered, as running a guess could be `quit!` */
+        let name = ex_alias(name);
+        if !EX_COMMANDS.contains(&name) && Command::from_name(name).is_none() {
+            let message = match matching_names(name).first() {
+                Some(best) => format!("Not a command: {} (did you mean {}?)", name, best),
+                None => format!("Not a command: {}", name),
+            };
+            self.output.status_message.set_message(message);
+            return Ok(true);
+        }
+        match name {
+            /* like vim, a named buffer keeps its name and a copy is written */
+            "write" if !argument.is_empty() && self.output.editor_rows.filename.is_some() => {
+                let message = match self.output.editor_rows.save_to(Path::new(argument)) {
+                    Ok(len) => format!("{} bytes written to {}", len, argument),
+                    Err(err) => format!("Can't write {}: {}", argument, err),
+                };
+                self.output.status_message.set_message(message)
+            }
+            "write" => {
+                if !argument.is_empty() && !self.output.is_read_only() {
+                    self.output.set_filename(PathBuf::from(argument))
+                }
+                self.save()?
+            }
+            "quit" => return Ok(!self.quit_saved()),
+            "quit!" => return Ok(false),
+            "wq" => {
+                self.save()?;
+                if self.output.dirty == 0 {
+                    return Ok(!self.quit_saved());
+                }
+            }
+            "edit" if argument.is_empty() => self
+                .output
+                .status_message
+                .set_message("edit needs a file name".into()),
+            "edit" => self.output.open_buffer(PathBuf::from(argument)),
+            "set" => self.output.set_options(argument),
+            "syntax" => self.output.set_syntax_by_name(argument),
+            "theme" => self.output.set_theme(argument),
+            name => {
+                let command = Command::from_name(name).unwrap();
+                if argument.is_empty() {
+                    return self.execute(command);
+                }
+                self.output
+                    .status_message
+                    .set_message(format!("{} takes no argument", name))
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_score_needs_every_character_in_order() {
+        assert_eq!(fuzzy_score("", "save"), Some(0));
+        assert_eq!(fuzzy_score("sv", "save"), Some(4));
+        assert_eq!(fuzzy_score("vs", "save"), None);
+        assert_eq!(fuzzy_score("saves", "save"), None);
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_runs_and_word_starts() {
+        assert_eq!(fuzzy_score("sa", "save"), Some(8));
+        assert_eq!(fuzzy_score("SA", "save"), Some(8));
+        assert_eq!(fuzzy_score("ff", "find-file"), Some(6));
+        assert!(fuzzy_score("nb", "next-buffer") > fuzzy_score("nb", "cycle-line-numbers"));
+    }
+
+    #[test]
+    fn matching_names_puts_the_best_and_shortest_first() {
+        assert_eq!(matching_names("quit")[..2], ["quit", "quit!"]);
+        assert_eq!(matching_names("nb")[0], "next-buffer");
+        assert!(matching_names("zz").is_empty());
+    }
+}
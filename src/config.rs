@@ -0,0 +1,404 @@
+//! User settings, read from `$XDG_CONFIG_HOME/pound/config.toml` (or
+//! `~/.config/pound/config.toml`). Every key is optional:
+//!
+//! ```toml
+//! tab_width = 8            # columns a tab character takes on screen
+//! indent = "tabs"          # what the Tab key inserts: "tabs" or "spaces"
+//! indent_width = 4         # spaces inserted by Tab when indent = "spaces"
+//! status_timeout = 5       # seconds a status bar message stays up
+//! quit_times = 3           # extra Ctrl-Q presses needed to quit with unsaved changes
+//! theme = "default"        # "default", "light", "gruvbox", one in themes/ or a file
+//! color_depth = "auto"     # "truecolor", "256", "16" or "none"; "auto" goes by NO_COLOR,
+//!                          # COLORTERM and TERM
+//! vim = false              # start in vim-style modal editing (toggled with Alt-M)
+//! keymap = "default"       # built in bindings: "default" or "emacs"
+//!
+//! [colors]                 # overrides single styles of the theme, as theme files give them
+//! comment = "dark_grey"    # a colour name, "#rrggbb" or a 256-colour index
+//! keyword = { fg = "yellow", bold = true }
+//!
+//! [filetype.rust]          # per file type, named as in the status bar
+//! indent = "spaces"
+//! tab_width = 4
+//!
+//! [keys]                   # key sequences to command names, "none" to unbind
+//! "Ctrl-K Ctrl-S" = "save"
+//! "Ctrl-Q" = "none"
+//! ```
+
+use crate::keymap::{self, describe_chord, Command, Keymap, Preset};
+use crate::palette::ColorDepth;
+use crate::theme::{Style, Theme};
+use crossterm::event::KeyEvent;
+use crossterm::style::Color;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fs, io};
+use toml::{Table, Value};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Indentation {
+    pub tab_width: usize,
+    pub style: IndentStyle,
+    pub indent_width: usize,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self {
+            tab_width: 8,
+            style: IndentStyle::Tabs,
+            indent_width: 4,
+        }
+    }
+}
+
+/// Indentation settings given for one file type; unset ones fall back to the global ones
+#[derive(Default)]
+struct IndentationOverride {
+    tab_width: Option<usize>,
+    style: Option<IndentStyle>,
+    indent_width: Option<usize>,
+}
+
+/// Parses TOML, describing a problem as `line N: problem`
+pub fn parse_toml(text: &str) -> Result<Table, String> {
+    text.parse::<Table>().map_err(|err| {
+        let line = err
+            .span()
+            .map(|span| text[..span.start].matches('\n').count() + 1);
+        let problem = err.message().trim().replace('\n', "; ");
+        match line {
+            Some(line) => format!("line {}: {}", line, problem),
+            None => problem,
+        }
+    })
+}
+
+/// Reads a colour as a name such as `"dark_green"`, `"#rrggbb"` or a 256-colour index
+pub fn parse_color(value: &Value) -> Option<Color> {
+    if let Some(index) = value.as_integer() {
+        return u8::try_from(index).ok().map(Color::AnsiValue);
+    }
+    let name = value.as_str()?;
+    if let Some(hex) = name.strip_prefix('#') {
+        let rgb = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        return Some(Color::Rgb {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        });
+    }
+    let color = match name {
+        "default" | "reset" => Color::Reset,
+        "black" => Color::Black,
+        "dark_grey" => Color::DarkGrey,
+        "red" => Color::Red,
+        "dark_red" => Color::DarkRed,
+        "green" => Color::Green,
+        "dark_green" => Color::DarkGreen,
+        "yellow" => Color::Yellow,
+        "dark_yellow" => Color::DarkYellow,
+        "blue" => Color::Blue,
+        "dark_blue" => Color::DarkBlue,
+        "magenta" => Color::Magenta,
+        "dark_magenta" => Color::DarkMagenta,
+        "cyan" => Color::Cyan,
+        "dark_cyan" => Color::DarkCyan,
+        "white" => Color::White,
+        "grey" => Color::Grey,
+        _ => return None,
+    };
+    Some(color)
+}
+
+pub struct Config {
+    pub indentation: Indentation,
+    pub status_timeout: Duration,
+    pub quit_times: u8,
+    pub theme: Theme,
+    /// What the terminal can show, which every theme is fitted to
+    pub color_depth: ColorDepth,
+    /// The `[colors]` styles, kept to be laid over a theme chosen later
+    colors: Vec<(String, Style)>,
+    pub vim: bool,
+    pub preset: Preset,
+    /// Bindings on top of the default keymap; `None` removes a binding
+    pub keys: Vec<(Vec<KeyEvent>, Option<Command>)>,
+    filetypes: HashMap<String, IndentationOverride>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut config = Self {
+            indentation: Indentation::default(),
+            status_timeout: Duration::from_secs(5),
+            quit_times: 3,
+            theme: Theme::default(),
+            color_depth: ColorDepth::detect(),
+            colors: Vec::new(),
+            vim: false,
+            preset: Preset::Default,
+            keys: Vec::new(),
+            filetypes: HashMap::new(),
+        };
+        config.use_theme(Theme::load("default").unwrap_or_default());
+        config
+    }
+}
+
+/// Collects what was wrong with the file while the usable parts are still applied
+struct Warnings(Vec<String>);
+
+impl Warnings {
+    fn push(&mut self, key: &str, problem: &str) {
+        self.0.push(format!("config: {} {}", key, problem))
+    }
+
+    fn unknown(&mut self, key: &str) {
+        self.push(key, "is not a known setting")
+    }
+
+    fn usize(&mut self, key: &str, value: &Value) -> Option<usize> {
+        let number = value
+            .as_integer()
+            .and_then(|number| usize::try_from(number).ok())
+            .filter(|number| *number > 0);
+        if number.is_none() {
+            self.push(key, "must be a positive whole number")
+        }
+        number
+    }
+
+    fn indent_style(&mut self, key: &str, value: &Value) -> Option<IndentStyle> {
+        match value.as_str() {
+            Some("tabs") => Some(IndentStyle::Tabs),
+            Some("spaces") => Some(IndentStyle::Spaces),
+            _ => {
+                self.push(key, "must be \"tabs\" or \"spaces\"");
+                None
+            }
+        }
+    }
+
+    fn table<'a>(&mut self, key: &str, value: &'a Value) -> Option<&'a Table> {
+        let table = value.as_table();
+        if table.is_none() {
+            self.push(key, "must be a table")
+        }
+        table
+    }
+}
+
+impl Config {
+    /// Where pound's settings live, `$XDG_CONFIG_HOME/pound` or `~/.config/pound`
+    pub fn directory() -> Option<PathBuf> {
+        let directory = env::var_os("XDG_CONFIG_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(directory.join("pound"))
+    }
+
+    fn path() -> Option<PathBuf> {
+        Some(Self::directory()?.join("config.toml"))
+    }
+
+    /// Reads the user's config file, returning the defaults for anything missing or invalid
+    /// along with warnings for the status bar
+    pub fn load() -> (Self, Vec<String>) {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return (Self::default(), Vec::new()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (Self::default(), Vec::new()),
+            Err(err) => (
+                Self::default(),
+                vec![format!("config: can't read {}: {}", path.display(), err)],
+            ),
+        }
+    }
+
+    pub fn parse(text: &str) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut warnings = Warnings(Vec::new());
+        let table = match parse_toml(text) {
+            Ok(table) => table,
+            Err(problem) => return (config, vec![format!("config: {}", problem)]),
+        };
+        /* the colour depth and theme come first so that [colors] applies on top of them
+        whatever the key order */
+        if let Some(value) = table.get("color_depth") {
+            match value.as_str().and_then(ColorDepth::from_name) {
+                Some(depth) => {
+                    config.color_depth = depth.unwrap_or_else(ColorDepth::detect);
+                    config.use_theme(Theme::load("default").unwrap_or_default())
+                }
+                None => warnings.push(
+                    "color_depth",
+                    "must be \"auto\", \"truecolor\", \"256\", \"16\" or \"none\"",
+                ),
+            }
+        }
+        if let Some(value) = table.get("theme") {
+            match value.as_str().map(Theme::load) {
+                Some(Ok(theme)) => config.use_theme(theme),
+                Some(Err(problem)) => warnings.push("theme", &problem),
+                None => warnings.push("theme", "must be a theme name"),
+            }
+        }
+        for (key, value) in &table {
+            match key.as_str() {
+                "tab_width" => {
+                    if let Some(width) = warnings.usize(key, value) {
+                        config.indentation.tab_width = width
+                    }
+                }
+                "indent" => {
+                    if let Some(style) = warnings.indent_style(key, value) {
+                        config.indentation.style = style
+                    }
+                }
+                "indent_width" => {
+                    if let Some(width) = warnings.usize(key, value) {
+                        config.indentation.indent_width = width
+                    }
+                }
+                "status_timeout" => {
+                    if let Some(seconds) = warnings.usize(key, value) {
+                        config.status_timeout = Duration::from_secs(seconds as u64)
+                    }
+                }
+                "quit_times" => match value.as_integer().and_then(|n| u8::try_from(n).ok()) {
+                    Some(times) => config.quit_times = times,
+                    None => warnings.push(key, "must be a whole number from 0 to 255"),
+                },
+                "theme" | "color_depth" => {}
+                "keymap" => match value.as_str().and_then(Preset::from_name) {
+                    Some(preset) => config.preset = preset,
+                    None => warnings.push(key, "must be \"default\" or \"emacs\""),
+                },
+                "vim" => match value.as_bool() {
+                    Some(vim) => config.vim = vim,
+                    None => warnings.push(key, "must be true or false"),
+                },
+                "colors" => {
+                    let colors = match warnings.table(key, value) {
+                        Some(colors) => colors,
+                        None => continue,
+                    };
+                    for (name, value) in colors {
+                        let key = format!("colors.{}", name);
+                        match Style::parse(value) {
+                            Ok(style) => {
+                                if config.theme.set(name, style) {
+                                    config.colors.push((name.clone(), style))
+                                } else {
+                                    warnings.unknown(&key)
+                                }
+                            }
+                            Err(problem) => warnings.push(&key, &problem),
+                        }
+                    }
+                }
+                "filetype" => {
+                    let filetypes = match warnings.table(key, value) {
+                        Some(filetypes) => filetypes,
+                        None => continue,
+                    };
+                    for (file_type, value) in filetypes {
+                        let prefix = format!("filetype.{}", file_type);
+                        let settings = match warnings.table(&prefix, value) {
+                            Some(settings) => settings,
+                            None => continue,
+                        };
+                        let mut indentation = IndentationOverride::default();
+                        for (key, value) in settings {
+                            let key = format!("{}.{}", prefix, key);
+                            match key.rsplit('.').next().unwrap() {
+                                "tab_width" => indentation.tab_width = warnings.usize(&key, value),
+                                "indent" => indentation.style = warnings.indent_style(&key, value),
+                                "indent_width" => {
+                                    indentation.indent_width = warnings.usize(&key, value)
+                                }
+                                _ => warnings.unknown(&key),
+                            }
+                        }
+                        config
+                            .filetypes
+                            .insert(file_type.to_lowercase(), indentation);
+                    }
+                }
+                "keys" => {
+                    let keys = match warnings.table(key, value) {
+                        Some(keys) => keys,
+                        None => continue,
+                    };
+                    for (chord, value) in keys {
+                        let key = format!("keys.\"{}\"", chord);
+                        let chord = match keymap::parse_chord(chord) {
+                            Some(chord) => chord,
+                            None => {
+                                warnings.push(&key, "is not a key sequence");
+                                continue;
+                            }
+                        };
+                        match value.as_str() {
+                            Some("none") => config.keys.push((chord, None)),
+                            Some(name) => match Command::from_name(name) {
+                                Some(command) => config.keys.push((chord, Some(command))),
+                                None => warnings.push(&key, "names an unknown command"),
+                            },
+                            None => warnings.push(&key, "must be a command name"),
+                        }
+                    }
+                }
+                _ => warnings.unknown(key),
+            }
+        }
+        let mut keymap = Keymap::new(config.preset);
+        for (keys, command) in &config.keys {
+            keymap.bind(keys.clone(), *command)
+        }
+        for (keys, command) in keymap.shadowed() {
+            let problem = format!(
+                "make {} a prefix, so its binding to {} is never reached",
+                describe_chord(keys),
+                command.name()
+            );
+            warnings.push("keys", &problem)
+        }
+        (config, warnings.0)
+    }
+
+    /// Switches to `theme`, fitted to the terminal, with the `[colors]` styles laid over it
+    pub fn use_theme(&mut self, mut theme: Theme) {
+        theme.fit(self.color_depth);
+        for (name, style) in &self.colors {
+            theme.set(name, *style);
+        }
+        self.theme = theme
+    }
+
+    /// Indentation for files of `file_type`, `None` meaning no known type
+    pub fn indentation(&self, file_type: Option<&str>) -> Indentation {
+        let mut indentation = self.indentation;
+        if let Some(overrides) = file_type.and_then(|it| self.filetypes.get(&it.to_lowercase())) {
+            indentation.tab_width = overrides.tab_width.unwrap_or(indentation.tab_width);
+            indentation.style = overrides.style.unwrap_or(indentation.style);
+            indentation.indent_width = overrides.indent_width.unwrap_or(indentation.indent_width);
+        }
+        indentation
+    }
+}
@@ -0,0 +1,250 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+
+/// Symlinks are followed by hand because the target of a dangling link may not exist yet
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Follows `path` through any symlinks to the file that actually holds the contents, so
+/// that saving replaces the target and keeps the link itself
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_HOPS {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other(format!(
+        "too many levels of symbolic links: {}",
+        path.display()
+    )))
+}
+
+fn temporary_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(format!(".pound-{}.tmp", process::id()));
+    target.with_file_name(name)
+}
+
+/// Replaces the contents of `path` without ever leaving it half written: the data goes to a
+/// temporary file in the same directory which is synced and then renamed over the original.
+/// If anything fails the original file is left untouched
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let target = resolve_symlinks(path)?;
+    let original = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    let temporary = temporary_path(&target);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temporary)?;
+    let result = (|| {
+        file.write_all(contents)?;
+        if let Some(metadata) = &original {
+            file.set_permissions(metadata.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::{fchown, MetadataExt};
+                /* only root may give files away; keeping our own ownership is fine otherwise */
+                let _ = fchown(&file, Some(metadata.uid()), Some(metadata.gid()));
+            }
+        }
+        file.sync_all()?;
+        fs::rename(&temporary, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+        return result;
+    }
+    /* make the rename itself durable */
+    #[cfg(unix)]
+    if let Some(directory) = target.parent() {
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+        let _ = fs::File::open(directory).and_then(|directory| directory.sync_all());
+    }
+    Ok(())
+}
+
+const BOM: char = '\u{feff}';
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+/// The parts of a file's layout that are not part of any row, kept so that saving writes
+/// them back the way they were read
+#[derive(Copy, Clone)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+    /// Both endings were found. The `'\r'` of the CRLF lines is then left at the end of
+    /// their rows, so that they are written back unchanged until the file is converted
+    pub mixed: bool,
+}
+
+impl FileFormat {
+    /// Used for files that don't exist yet
+    pub fn new() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+            mixed: false,
+        }
+    }
+
+    /// Detects the format of `contents` and splits it into rows. Only a file whose every
+    /// line ends in CRLF is taken as CRLF; otherwise any `'\r'` stays part of its row
+    pub fn parse(contents: &str) -> (Self, Vec<&str>) {
+        let (bom, contents) = match contents.strip_prefix(BOM) {
+            Some(rest) => (true, rest),
+            None => (false, contents),
+        };
+        let final_newline = contents.is_empty() || contents.ends_with('\n');
+        let mut rows: Vec<&str> = contents.split('\n').collect();
+        let last = rows.pop().unwrap_or_default();
+        let crlf = rows.iter().filter(|row| row.ends_with('\r')).count();
+        let mixed = crlf > 0 && crlf < rows.len();
+        let line_ending = match crlf > 0 && !mixed {
+            true => LineEnding::CrLf,
+            false => LineEnding::Lf,
+        };
+        if line_ending == LineEnding::CrLf {
+            rows.iter_mut()
+                .for_each(|row| *row = row.strip_suffix('\r').unwrap_or(row));
+        }
+        if !final_newline {
+            rows.push(last)
+        }
+        let format = Self {
+            line_ending,
+            final_newline,
+            bom,
+            mixed,
+        };
+        (format, rows)
+    }
+
+    pub fn serialize<'a>(&self, rows: impl Iterator<Item = &'a str>) -> String {
+        let mut contents = String::new();
+        if self.bom {
+            contents.push(BOM)
+        }
+        let mut rows = rows.peekable();
+        while let Some(row) = rows.next() {
+            contents.push_str(row);
+            if rows.peek().is_some() || self.final_newline {
+                contents.push_str(self.line_ending.as_str())
+            }
+        }
+        contents
+    }
+}
+
+/// How much of a file is checked for NUL bytes when deciding whether it is binary
+const BINARY_CHECK_LENGTH: usize = 8192;
+
+/// Returns the text of `bytes` if it looks like a UTF-8 text file
+pub fn decode(bytes: &[u8]) -> Option<&str> {
+    if bytes
+        .iter()
+        .take(BINARY_CHECK_LENGTH)
+        .any(|byte| *byte == 0)
+    {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()
+}
+
+/// Decodes anything, replacing invalid sequences and control characters other than tabs
+/// and line breaks with U+FFFD so they can't reach the terminal
+pub fn decode_lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .map(|c| {
+            if c.is_control() && !matches!(c, '\t' | '\n' | '\r') {
+                char::REPLACEMENT_CHARACTER
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+static ESCAPE: OnceLock<regex::bytes::Regex> = OnceLock::new();
+
+/// Drops terminal escape sequences, such as the colours `git` and `cargo` write, from
+/// piped input so they are neither shown as text nor sent to the terminal
+pub fn strip_escapes(bytes: &[u8]) -> Vec<u8> {
+    let escape = ESCAPE.get_or_init(|| {
+        regex::bytes::Regex::new(
+            r"\x1b(\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(\x07|\x1b\\)?|[@-Z\\-_])",
+        )
+        .unwrap()
+    });
+    escape.replace_all(bytes, &b""[..]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(contents: &str) -> (FileFormat, String) {
+        let (format, rows) = FileFormat::parse(contents);
+        (format, format.serialize(rows.into_iter()))
+    }
+
+    #[test]
+    fn keeps_line_endings() {
+        for contents in ["a\nb\n", "a\r\nb\r\n", "a\nb", "\u{feff}a\r\nb", ""] {
+            assert_eq!(round_trip(contents).1, contents);
+        }
+        assert!(FileFormat::parse("a\r\nb\r\n").0.line_ending == LineEnding::CrLf);
+    }
+
+    #[test]
+    fn leaves_mixed_line_endings_alone() {
+        for contents in ["a\r\nb\nc\r\n", "a\nb\r\nc"] {
+            let (format, written) = round_trip(contents);
+            assert!(format.mixed && format.line_ending == LineEnding::Lf);
+            assert_eq!(written, contents);
+        }
+    }
+}
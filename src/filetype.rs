@@ -0,0 +1,100 @@
+//! Working out a file's type from more than its extension: glob patterns, the `#!` line and
+//! the modelines Vim and Emacs read
+
+/// Matches `text` against `pattern`, where `*` stands for any run of characters and `?` for
+/// any one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    /* where the last `*` was and how much of `text` it has taken so far */
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1
+            }
+            Some(c) if *c == b'?' || *c == text[t] => {
+                p += 1;
+                t += 1
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1))
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// The interpreter a `#!` line runs, looking through `env`
+pub fn interpreter(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        words.find(|word| !word.starts_with('-') && !word.contains('='))
+    } else {
+        Some(program)
+    }
+}
+
+/// The file type named by `-*- mode: python -*-` or `-*- python -*-`
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+    if !variables.contains(':') {
+        return Some(variables.trim()).filter(|mode| !mode.is_empty());
+    }
+    variables.split(';').find_map(|variable| {
+        let (name, value) = variable.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("mode")
+            .then(|| value.trim())
+    })
+}
+
+/// The file type set by `vim: set ft=python :`, `vi: filetype=python` and the like
+fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(index, _)| *index == 0 || line[..*index].ends_with(char::is_whitespace))
+            .map(|(index, _)| index + marker.len())
+    })?;
+    let options = line[start..].trim_start();
+    let options = options
+        .strip_prefix("set ")
+        .or_else(|| options.strip_prefix("se "))
+        .unwrap_or(options);
+    options
+        .split([' ', '\t', ':'])
+        .find_map(|option| {
+            let (name, value) = option.split_once('=')?;
+            matches!(name, "ft" | "filetype" | "syn" | "syntax").then_some(value)
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// How many rows at either end of a file are searched for a Vim modeline, as Vim does
+pub const MODELINES: usize = 5;
+
+/// The file type a modeline in `text` asks for. Emacs reads its line from the top of the
+/// file, or the second row after a `#!` line
+pub fn modeline(text: &str) -> Option<&str> {
+    let mut lines = text.lines();
+    let first = lines.next()?;
+    let emacs = if first.starts_with("#!") {
+        lines.next().and_then(emacs_modeline)
+    } else {
+        emacs_modeline(first)
+    };
+    emacs.or_else(|| {
+        text.lines()
+            .take(MODELINES)
+            .chain(text.lines().rev().take(MODELINES))
+            .find_map(vim_modeline)
+    })
+}
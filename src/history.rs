@@ -0,0 +1,400 @@
+use crate::Position;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A single change to the buffer. `text` may span several rows, in which case it
+/// contains `'\n'` at every row boundary
+#[derive(Clone)]
+pub enum Edit {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+}
+
+impl Edit {
+    fn inverse(&self) -> Self {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete {
+                at: *at,
+                text: text.clone(),
+            },
+            Edit::Delete { at, text } => Edit::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+        }
+    }
+
+    /// Tries to fold `next` into this edit so that typing a word or holding
+    /// backspace is undone in one step
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::Insert { at, text },
+                Edit::Insert {
+                    at: next_at,
+                    text: next_text,
+                },
+            ) => {
+                let word_boundary = text.ends_with(char::is_whitespace)
+                    && !next_text.starts_with(char::is_whitespace);
+                if text.contains('\n')
+                    || next_text.contains('\n')
+                    || word_boundary
+                    || text_end(*at, text) != *next_at
+                {
+                    return false;
+                }
+                text.push_str(next_text);
+                true
+            }
+            (
+                Edit::Delete { at, text },
+                Edit::Delete {
+                    at: next_at,
+                    text: next_text,
+                },
+            ) => {
+                if text.contains('\n') || next_text.contains('\n') || at.y != next_at.y {
+                    return false;
+                }
+                if *next_at == *at {
+                    text.push_str(next_text);
+                } else if text_end(*next_at, next_text) == *at {
+                    text.insert_str(0, next_text);
+                    *at = *next_at;
+                } else {
+                    return false;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the position just past `text` when it is inserted at `at`, counting graphemes
+/// as `Position::x` does
+pub fn text_end(at: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        None => Position {
+            x: at.x + text.graphemes(true).count(),
+            y: at.y,
+        },
+        Some(index) => Position {
+            x: text[index + 1..].graphemes(true).count(),
+            y: at.y + text.matches('\n').count(),
+        },
+    }
+}
+
+struct Operation {
+    edits: Vec<Edit>,
+    cursor_before: Position,
+    cursor_after: Position,
+}
+
+pub struct History {
+    undo_stack: Vec<Operation>,
+    redo_stack: Vec<Operation>,
+    group: Option<Operation>,
+    group_depth: usize,
+    sealed: bool,
+    save_point: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            group: None,
+            group_depth: 0,
+            sealed: true,
+            save_point: Some(0),
+        }
+    }
+
+    pub fn record(&mut self, edit: Edit, cursor_before: Position, cursor_after: Position) {
+        if !self.redo_stack.is_empty() {
+            self.redo_stack.clear();
+            if matches!(self.save_point, Some(point) if point > self.undo_stack.len()) {
+                self.save_point = None
+            }
+        }
+        if let Some(group) = self.group.as_mut() {
+            group.edits.push(edit);
+            group.cursor_after = cursor_after;
+            return;
+        }
+        if !self.sealed {
+            if let Some(operation) = self.undo_stack.last_mut() {
+                if operation.edits.last_mut().unwrap().merge(&edit) {
+                    operation.cursor_after = cursor_after;
+                    return;
+                }
+            }
+        }
+        self.undo_stack.push(Operation {
+            edits: vec![edit],
+            cursor_before,
+            cursor_after,
+        });
+        self.sealed = false;
+    }
+
+    /// Adds `edit` to the last operation whether or not it is sealed, for an edit that
+    /// only makes sense together with the one before
+    pub fn record_joined(&mut self, edit: Edit, cursor_after: Position) {
+        match self.group.as_mut().or(self.undo_stack.last_mut()) {
+            Some(operation) => {
+                operation.edits.push(edit);
+                operation.cursor_after = cursor_after
+            }
+            None => self.record(edit, cursor_after, cursor_after),
+        }
+    }
+
+    /// Starts collecting edits into one operation, e.g. when typing over a selection.
+    /// Groups may nest; only the outermost one ends up on the undo stack
+    pub fn begin_group(&mut self, cursor: Position) {
+        if self.group_depth == 0 {
+            self.group = Some(Operation {
+                edits: Vec::new(),
+                cursor_before: cursor,
+                cursor_after: cursor,
+            })
+        }
+        self.group_depth += 1
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth > 0 {
+            return;
+        }
+        if let Some(group) = self.group.take() {
+            if !group.edits.is_empty() {
+                self.undo_stack.push(group);
+            }
+        }
+        self.seal()
+    }
+
+    /// Makes the next edit start a new operation instead of merging into the last one
+    pub fn seal(&mut self) {
+        self.sealed = true
+    }
+
+    /// Returns the edits that revert the last operation and where to put the cursor
+    pub fn undo(&mut self) -> Option<(Vec<Edit>, Position)> {
+        self.seal();
+        let operation = self.undo_stack.pop()?;
+        let edits = operation.edits.iter().rev().map(Edit::inverse).collect();
+        let cursor = operation.cursor_before;
+        self.redo_stack.push(operation);
+        Some((edits, cursor))
+    }
+
+    pub fn redo(&mut self) -> Option<(Vec<Edit>, Position)> {
+        self.seal();
+        let operation = self.redo_stack.pop()?;
+        let edits = operation.edits.clone();
+        let cursor = operation.cursor_after;
+        self.undo_stack.push(operation);
+        Some((edits, cursor))
+    }
+
+    /// Where the undo stack stands, for a later `squash`
+    pub fn checkpoint(&mut self) -> usize {
+        self.seal();
+        self.undo_stack.len()
+    }
+
+    /// Folds every operation since `checkpoint` into one, so that a whole vim insert is
+    /// undone in one step however it was typed
+    pub fn squash(&mut self, checkpoint: usize) {
+        self.seal();
+        let length = self.undo_stack.len();
+        if length <= checkpoint + 1 {
+            return;
+        }
+        self.save_point = match self.save_point {
+            Some(point) if point == length => Some(checkpoint + 1),
+            Some(point) if point > checkpoint => None,
+            point => point,
+        };
+        let mut operations = self.undo_stack.split_off(checkpoint).into_iter();
+        let mut squashed = operations.next().unwrap();
+        for operation in operations {
+            squashed.edits.extend(operation.edits);
+            squashed.cursor_after = operation.cursor_after;
+        }
+        self.undo_stack.push(squashed);
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.seal();
+        self.save_point = Some(self.undo_stack.len())
+    }
+
+    /// For changes that undo can't revert, so the buffer never looks saved again until it is
+    pub fn forget_save_point(&mut self) {
+        self.save_point = None
+    }
+
+    pub fn is_at_save_point(&self) -> bool {
+        self.save_point == Some(self.undo_stack.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn insert(x: usize, y: usize, text: &str) -> Edit {
+        Edit::Insert {
+            at: at(x, y),
+            text: text.into(),
+        }
+    }
+
+    fn delete(x: usize, y: usize, text: &str) -> Edit {
+        Edit::Delete {
+            at: at(x, y),
+            text: text.into(),
+        }
+    }
+
+    fn undo_text(history: &mut History) -> Vec<String> {
+        let (edits, _) = history.undo().unwrap();
+        edits
+            .into_iter()
+            .map(|edit| match edit {
+                Edit::Insert { text, .. } => format!("+{}", text),
+                Edit::Delete { text, .. } => format!("-{}", text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn text_end_counts_graphemes() {
+        assert_eq!(text_end(at(1, 0), "é"), at(2, 0));
+        assert_eq!(text_end(at(1, 0), "e\u{301}👍"), at(3, 0));
+        assert_eq!(text_end(at(4, 2), "ab\nçé"), at(2, 3));
+    }
+
+    #[test]
+    fn undoes_a_multibyte_insert() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "é"), at(0, 0), at(1, 0));
+        history.record(insert(1, 0, "ü"), at(1, 0), at(2, 0));
+        let (edits, cursor) = history.undo().unwrap();
+        assert_eq!(cursor, at(0, 0));
+        match &edits[..] {
+            [Edit::Delete { at: start, text }] => {
+                assert_eq!(*start, at(0, 0));
+                assert_eq!(text, "éü");
+                assert_eq!(text_end(*start, text), at(2, 0));
+            }
+            _ => panic!("expected one deletion"),
+        }
+    }
+
+    #[test]
+    fn merges_typing_up_to_a_word_boundary() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "a"), at(0, 0), at(1, 0));
+        history.record(insert(1, 0, "b"), at(1, 0), at(2, 0));
+        history.record(insert(2, 0, " "), at(2, 0), at(3, 0));
+        history.record(insert(3, 0, "c"), at(3, 0), at(4, 0));
+        history.record(insert(0, 1, "d"), at(0, 1), at(1, 1));
+        assert_eq!(undo_text(&mut history), ["-d"]);
+        assert_eq!(undo_text(&mut history), ["-c"]);
+        assert_eq!(undo_text(&mut history), ["-ab "]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn merges_backspace_and_delete_on_one_row() {
+        let mut history = History::new();
+        history.record(delete(2, 0, "c"), at(3, 0), at(2, 0));
+        history.record(delete(1, 0, "b"), at(2, 0), at(1, 0));
+        history.record(delete(1, 0, "d"), at(1, 0), at(1, 0));
+        history.record(delete(0, 1, "x"), at(0, 1), at(0, 1));
+        assert_eq!(undo_text(&mut history), ["+x"]);
+        let (edits, cursor) = history.undo().unwrap();
+        assert_eq!(cursor, at(3, 0));
+        match &edits[..] {
+            [Edit::Insert { at: start, text }] => {
+                assert_eq!(*start, at(1, 0));
+                assert_eq!(text, "bcd");
+            }
+            _ => panic!("expected one insertion"),
+        }
+    }
+
+    #[test]
+    fn nested_groups_undo_as_one() {
+        let mut history = History::new();
+        history.begin_group(at(0, 0));
+        history.record(delete(0, 0, "ab"), at(0, 0), at(0, 0));
+        history.begin_group(at(0, 0));
+        history.record(insert(0, 0, "x"), at(0, 0), at(1, 0));
+        history.end_group();
+        history.record(insert(1, 0, "y"), at(1, 0), at(2, 0));
+        history.end_group();
+        history.record(insert(2, 0, "z"), at(2, 0), at(3, 0));
+        assert_eq!(undo_text(&mut history), ["-z"]);
+        assert_eq!(undo_text(&mut history), ["-y", "-x", "+ab"]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn squash_folds_everything_since_the_checkpoint() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "a"), at(0, 0), at(1, 0));
+        let checkpoint = history.checkpoint();
+        history.record(insert(1, 0, "b"), at(1, 0), at(2, 0));
+        history.record(insert(2, 0, "\n"), at(2, 0), at(0, 1));
+        history.record(insert(0, 1, "c"), at(0, 1), at(1, 1));
+        history.squash(checkpoint);
+        let (edits, cursor) = history.undo().unwrap();
+        assert_eq!(edits.len(), 3);
+        assert_eq!(cursor, at(1, 0));
+        assert_eq!(undo_text(&mut history), ["-a"]);
+        let (_, cursor) = history.redo().unwrap();
+        assert_eq!(cursor, at(1, 0));
+        let (_, cursor) = history.redo().unwrap();
+        assert_eq!(cursor, at(1, 1));
+    }
+
+    #[test]
+    fn save_point_is_lost_by_editing_after_undo() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "a"), at(0, 0), at(1, 0));
+        history.mark_saved();
+        history.record(insert(1, 0, "b"), at(1, 0), at(2, 0));
+        assert!(!history.is_at_save_point());
+        history.undo();
+        assert!(history.is_at_save_point());
+        history.undo();
+        assert!(!history.is_at_save_point());
+        history.record(insert(0, 0, "c"), at(0, 0), at(1, 0));
+        assert!(!history.is_at_save_point());
+        history.undo();
+        assert!(!history.is_at_save_point());
+    }
+
+    #[test]
+    fn joined_edits_ignore_the_seal() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "("), at(0, 0), at(1, 0));
+        history.seal();
+        history.record_joined(insert(1, 0, ")"), at(1, 0));
+        assert_eq!(undo_text(&mut history), ["-)", "-("]);
+        assert!(history.undo().is_none());
+    }
+}
@@ -0,0 +1,468 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{HashMap, HashSet};
+
+macro_rules! commands {
+    ($($Variant:ident => $name:expr),* $(,)?) => {
+        /// Everything a key can be bound to, named as in the config file
+        #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum Command {
+            $($Variant),*
+        }
+
+        impl Command {
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Command::$Variant => $name),*
+                }
+            }
+
+            pub fn all() -> &'static [Command] {
+                &[$(Command::$Variant),*]
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Command::$Variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+commands! {
+    Quit => "quit",
+    Save => "save",
+    Find => "find",
+    Replace => "replace",
+    OpenFile => "open-file",
+    GoToLine => "go-to-line",
+    ChooseSyntax => "choose-syntax",
+    ListBuffers => "list-buffers",
+    NextBuffer => "next-buffer",
+    PreviousBuffer => "previous-buffer",
+    SplitHorizontal => "split-horizontal",
+    SplitVertical => "split-vertical",
+    NextWindow => "next-window",
+    CloseWindow => "close-window",
+    ToggleReadOnly => "toggle-read-only",
+    CycleLineNumbers => "cycle-line-numbers",
+    LineEndingsLf => "line-endings-lf",
+    LineEndingsCrlf => "line-endings-crlf",
+    Undo => "undo",
+    Redo => "redo",
+    Copy => "copy",
+    Cut => "cut",
+    Paste => "paste",
+    MoveUp => "move-up",
+    MoveDown => "move-down",
+    MoveLeft => "move-left",
+    MoveRight => "move-right",
+    MoveHome => "move-home",
+    MoveEnd => "move-end",
+    PageUp => "page-up",
+    PageDown => "page-down",
+    SelectUp => "select-up",
+    SelectDown => "select-down",
+    SelectLeft => "select-left",
+    SelectRight => "select-right",
+    SelectHome => "select-home",
+    SelectEnd => "select-end",
+    SelectPageUp => "select-page-up",
+    SelectPageDown => "select-page-down",
+    DeleteBackward => "delete-backward",
+    DeleteForward => "delete-forward",
+    InsertNewline => "insert-newline",
+    InsertTab => "insert-tab",
+    DescribeKey => "describe-key",
+    ToggleVimMode => "toggle-vim-mode",
+    SetMark => "set-mark",
+    Cancel => "cancel",
+    KillLine => "kill-line",
+    KillRegion => "kill-region",
+    CopyRegion => "copy-region",
+    Yank => "yank",
+    YankPop => "yank-pop",
+    Palette => "command-palette",
+}
+
+impl Command {
+    /// The movements that extend the selection rather than drop it
+    pub fn is_selection(self) -> bool {
+        matches!(
+            self,
+            Command::SelectUp
+                | Command::SelectDown
+                | Command::SelectLeft
+                | Command::SelectRight
+                | Command::SelectHome
+                | Command::SelectEnd
+                | Command::SelectPageUp
+                | Command::SelectPageDown
+        )
+    }
+}
+
+/// Terminals report shifted letters as the upper case character, sometimes with SHIFT set
+/// as well; bindings are stored without it so that both forms match
+pub fn normalize(key: KeyEvent) -> KeyEvent {
+    match key.code {
+        KeyCode::Char(_) => KeyEvent::new(key.code, key.modifiers - KeyModifiers::SHIFT),
+        _ => key,
+    }
+}
+
+/// Parses one key such as `Ctrl-S`, `C-x`, `Alt-Left` or `PageDown`
+fn parse_key(text: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = text;
+    loop {
+        let (modifier, length) = match rest.split_once('-') {
+            Some((prefix, _)) if !prefix.is_empty() && rest.len() > prefix.len() + 1 => {
+                match prefix.to_ascii_lowercase().as_str() {
+                    "c" | "ctrl" => (KeyModifiers::CONTROL, prefix.len() + 1),
+                    "m" | "alt" | "meta" => (KeyModifiers::ALT, prefix.len() + 1),
+                    "s" | "shift" => (KeyModifiers::SHIFT, prefix.len() + 1),
+                    _ => return None,
+                }
+            }
+            _ => break,
+        };
+        modifiers |= modifier;
+        rest = &rest[length..];
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        /* terminals send the same byte for Ctrl-/, Ctrl-_ and Ctrl-7 */
+        (Some('/' | '_'), None) if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char('7'),
+        (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "enter" | "return" => KeyCode::Enter,
+            "tab" if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers -= KeyModifiers::SHIFT;
+                KeyCode::BackTab
+            }
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "esc" | "escape" => KeyCode::Esc,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => KeyCode::F(name.strip_prefix('f')?.parse().ok()?),
+        },
+    };
+    Some(normalize(KeyEvent::new(code, modifiers)))
+}
+
+/// Parses a space separated sequence of keys, e.g. `Ctrl-X Ctrl-S`
+pub fn parse_chord(text: &str) -> Option<Vec<KeyEvent>> {
+    let keys = text
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Option<Vec<KeyEvent>>>()?;
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys)
+    }
+}
+
+fn describe_key(key: &KeyEvent) -> String {
+    let mut description = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        description.push_str("Ctrl-")
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        description.push_str("Alt-")
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        description.push_str("Shift-")
+    }
+    match key.code {
+        KeyCode::Char(' ') => description.push_str("Space"),
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            description.push(c.to_ascii_uppercase())
+        }
+        KeyCode::Char(c) => description.push(c),
+        KeyCode::F(number) => description.push_str(&format!("F{}", number)),
+        KeyCode::BackTab => description.push_str("Shift-Tab"),
+        KeyCode::PageUp => description.push_str("PageUp"),
+        KeyCode::PageDown => description.push_str("PageDown"),
+        code => description.push_str(&format!("{:?}", code)),
+    }
+    description
+}
+
+pub fn describe_chord(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(describe_key)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub enum Lookup {
+    Command(Command),
+    /// The keys so far start one or more longer bindings
+    Prefix,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<KeyEvent>, Command>,
+    prefixes: HashSet<Vec<KeyEvent>>,
+}
+
+/// The built in bindings the keymap starts from, before those in the config file
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    Default,
+    Emacs,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Preset::Default),
+            "emacs" => Some(Preset::Emacs),
+            _ => None,
+        }
+    }
+}
+
+/// Bindings every preset shares
+const COMMON_BINDINGS: &[(&str, Command)] = &[
+    ("Up", Command::MoveUp),
+    ("Down", Command::MoveDown),
+    ("Left", Command::MoveLeft),
+    ("Right", Command::MoveRight),
+    ("Home", Command::MoveHome),
+    ("End", Command::MoveEnd),
+    ("PageUp", Command::PageUp),
+    ("PageDown", Command::PageDown),
+    ("Shift-Up", Command::SelectUp),
+    ("Shift-Down", Command::SelectDown),
+    ("Shift-Left", Command::SelectLeft),
+    ("Shift-Right", Command::SelectRight),
+    ("Shift-Home", Command::SelectHome),
+    ("Shift-End", Command::SelectEnd),
+    ("Shift-PageUp", Command::SelectPageUp),
+    ("Shift-PageDown", Command::SelectPageDown),
+    ("Backspace", Command::DeleteBackward),
+    ("Delete", Command::DeleteForward),
+    ("Enter", Command::InsertNewline),
+    ("Tab", Command::InsertTab),
+    ("Alt-k", Command::DescribeKey),
+    ("Alt-m", Command::ToggleVimMode),
+    ("Alt-n", Command::CycleLineNumbers),
+];
+
+const DEFAULT_BINDINGS: &[(&str, Command)] = &[
+    ("Ctrl-Q", Command::Quit),
+    ("Ctrl-P", Command::Palette),
+    ("Ctrl-S", Command::Save),
+    ("Ctrl-F", Command::Find),
+    ("Ctrl-R", Command::Replace),
+    ("Ctrl-O", Command::OpenFile),
+    ("Ctrl-G", Command::GoToLine),
+    ("Ctrl-T", Command::ChooseSyntax),
+    ("Ctrl-B", Command::ListBuffers),
+    ("Alt-Right", Command::NextBuffer),
+    ("Alt-Left", Command::PreviousBuffer),
+    ("Alt-s", Command::SplitHorizontal),
+    ("Alt-v", Command::SplitVertical),
+    ("Alt-o", Command::NextWindow),
+    ("Alt-q", Command::CloseWindow),
+    ("Alt-r", Command::ToggleReadOnly),
+    ("Alt-u", Command::LineEndingsLf),
+    ("Alt-d", Command::LineEndingsCrlf),
+    ("Ctrl-Z", Command::Undo),
+    ("Ctrl-Y", Command::Redo),
+    ("Ctrl-C", Command::Copy),
+    ("Ctrl-X", Command::Cut),
+    ("Ctrl-V", Command::Paste),
+];
+
+const EMACS_BINDINGS: &[(&str, Command)] = &[
+    ("Ctrl-A", Command::MoveHome),
+    ("Ctrl-E", Command::MoveEnd),
+    ("Ctrl-N", Command::MoveDown),
+    ("Ctrl-P", Command::MoveUp),
+    ("Ctrl-F", Command::MoveRight),
+    ("Ctrl-B", Command::MoveLeft),
+    ("Ctrl-V", Command::PageDown),
+    ("Alt-v", Command::PageUp),
+    ("Ctrl-D", Command::DeleteForward),
+    ("Ctrl-Space", Command::SetMark),
+    ("Ctrl-G", Command::Cancel),
+    ("Alt-x", Command::Palette),
+    ("Ctrl-K", Command::KillLine),
+    ("Ctrl-W", Command::KillRegion),
+    ("Alt-w", Command::CopyRegion),
+    ("Ctrl-Y", Command::Yank),
+    ("Alt-y", Command::YankPop),
+    ("Ctrl-S", Command::Find),
+    ("Alt-%", Command::Replace),
+    ("Ctrl-/", Command::Undo),
+    ("Ctrl-Alt-/", Command::Redo),
+    ("Alt-g g", Command::GoToLine),
+    ("Alt-g Alt-g", Command::GoToLine),
+    ("Ctrl-X Ctrl-S", Command::Save),
+    ("Ctrl-X Ctrl-C", Command::Quit),
+    ("Ctrl-X Ctrl-F", Command::OpenFile),
+    ("Ctrl-X Ctrl-Q", Command::ToggleReadOnly),
+    ("Ctrl-X Ctrl-T", Command::ChooseSyntax),
+    ("Ctrl-X u", Command::Undo),
+    ("Ctrl-X b", Command::ListBuffers),
+    ("Ctrl-X Right", Command::NextBuffer),
+    ("Ctrl-X Left", Command::PreviousBuffer),
+    ("Ctrl-X 2", Command::SplitHorizontal),
+    ("Ctrl-X 3", Command::SplitVertical),
+    ("Ctrl-X o", Command::NextWindow),
+    ("Ctrl-X 0", Command::CloseWindow),
+];
+
+impl Keymap {
+    pub fn new(preset: Preset) -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+            prefixes: HashSet::new(),
+        };
+        let preset_bindings = match preset {
+            Preset::Default => DEFAULT_BINDINGS,
+            Preset::Emacs => EMACS_BINDINGS,
+        };
+        for (chord, command) in COMMON_BINDINGS.iter().chain(preset_bindings) {
+            keymap.bind(parse_chord(chord).unwrap(), Some(*command))
+        }
+        keymap
+    }
+
+    /// Binds `keys` to `command`, or removes the binding for `None`
+    pub fn bind(&mut self, keys: Vec<KeyEvent>, command: Option<Command>) {
+        match command {
+            Some(command) => {
+                self.bindings.insert(keys, command);
+            }
+            None => {
+                self.bindings.remove(&keys);
+            }
+        }
+        self.prefixes = self
+            .bindings
+            .keys()
+            .flat_map(|keys| (1..keys.len()).map(move |length| keys[..length].to_vec()))
+            .collect();
+    }
+
+    /// Bindings that can't be reached, as their keys also start a longer sequence
+    pub fn shadowed(&self) -> Vec<(&[KeyEvent], Command)> {
+        let mut shadowed = self
+            .bindings
+            .iter()
+            .filter(|(keys, _)| self.prefixes.contains(*keys))
+            .map(|(keys, command)| (&keys[..], *command))
+            .collect::<Vec<_>>();
+        shadowed.sort_by_key(|(keys, _)| describe_chord(keys));
+        shadowed
+    }
+
+    /// The shortest key sequence that runs `command`, for messages that name it
+    pub fn chord_for(&self, command: Command) -> Option<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(keys, _)| describe_chord(keys))
+            .min_by_key(|chord| (chord.len(), chord.clone()))
+    }
+
+    pub fn lookup(&self, keys: &[KeyEvent]) -> Lookup {
+        if self.prefixes.contains(keys) {
+            return Lookup::Prefix;
+        }
+        match self.bindings.get(keys) {
+            Some(command) => Lookup::Command(*command),
+            None => Lookup::Unbound,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_chords() {
+        assert_eq!(
+            parse_chord("Ctrl-X  C-s"),
+            Some(vec![
+                key(KeyCode::Char('x'), KeyModifiers::CONTROL),
+                key(KeyCode::Char('s'), KeyModifiers::CONTROL),
+            ])
+        );
+        assert_eq!(
+            parse_chord("M-S-left"),
+            Some(vec![key(
+                KeyCode::Left,
+                KeyModifiers::ALT | KeyModifiers::SHIFT
+            )])
+        );
+        assert_eq!(
+            parse_chord("Shift-Tab F12 Space C--"),
+            Some(vec![
+                key(KeyCode::BackTab, KeyModifiers::NONE),
+                key(KeyCode::F(12), KeyModifiers::NONE),
+                key(KeyCode::Char(' '), KeyModifiers::NONE),
+                key(KeyCode::Char('-'), KeyModifiers::CONTROL),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_keys_as_terminals_send_them() {
+        assert_eq!(
+            parse_chord("Ctrl-/"),
+            Some(vec![key(KeyCode::Char('7'), KeyModifiers::CONTROL)])
+        );
+        assert_eq!(
+            parse_chord("Shift-A"),
+            Some(vec![key(KeyCode::Char('A'), KeyModifiers::NONE)])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_chords() {
+        for text in [
+            "",
+            "  ",
+            "Hyper-x",
+            "Ctrl-",
+            "Fx",
+            "PageSideways",
+            "C-x Bogus",
+        ] {
+            assert_eq!(parse_chord(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn describes_what_it_parses() {
+        let keys = parse_chord("C-x M-PageDown S-Tab").unwrap();
+        assert_eq!(describe_chord(&keys), "Ctrl-X Alt-PageDown Shift-Tab");
+        assert_eq!(parse_chord(&describe_chord(&keys)), Some(keys));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::*;
+use std::collections::VecDeque;
+
+/// How many kills are remembered before the oldest is dropped
+const KILL_RING_SIZE: usize = 60;
+
+/// Killed text, newest first, for the Emacs yank commands
+pub struct KillRing {
+    entries: VecDeque<String>,
+    /// The entry the last yank or yank-pop inserted
+    yank_index: usize,
+    /// Where that text went, for `yank-pop` to replace it
+    last_yank: Option<(Position, Position)>,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            yank_index: 0,
+            last_yank: None,
+        }
+    }
+
+    /// Adds a kill, or extends the newest one when killing straight after another kill.
+    /// `before` puts `text` in front, as killing backwards does
+    fn kill(&mut self, text: String, append: bool, before: bool) {
+        match self.entries.front_mut() {
+            Some(newest) if append => {
+                if before {
+                    newest.insert_str(0, &text)
+                } else {
+                    newest.push_str(&text)
+                }
+            }
+            _ => {
+                self.entries.push_front(text);
+                self.entries.truncate(KILL_RING_SIZE)
+            }
+        }
+    }
+}
+
+impl Output {
+    fn add_kill(&mut self, text: String, append: bool, before: bool) {
+        self.kill_ring.kill(text, append, before);
+        self.clipboard = self.kill_ring.entries.front().cloned()
+    }
+
+    /// Kills the rest of the line, or the line break when the cursor is already at its end
+    pub fn kill_line(&mut self, append: bool) {
+        if self.is_read_only() {
+            return;
+        }
+        let at = self.cursor_controller.position();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let end = if at.y >= number_of_rows {
+            None
+        } else if at.x < self.editor_rows.get_editor_row(at.y).len() {
+            Some(Position {
+                y: at.y,
+                x: self.editor_rows.get_editor_row(at.y).len(),
+            })
+        } else if at.y + 1 < number_of_rows {
+            Some(Position { y: at.y + 1, x: 0 })
+        } else {
+            None
+        };
+        let end = match end {
+            Some(end) => end,
+            None => {
+                self.status_message.set_message("End of buffer".into());
+                return;
+            }
+        };
+        self.cursor_controller.anchor = Some(end);
+        if let Some(text) = self.delete_selection() {
+            self.add_kill(text, append, false)
+        }
+    }
+
+    /// Kills the text between the mark and the cursor
+    pub fn kill_region(&mut self, append: bool) {
+        if self.is_read_only() {
+            return;
+        }
+        let before = self.cursor_controller.anchor > Some(self.cursor_controller.position());
+        self.history.seal();
+        match self.delete_selection() {
+            Some(text) => {
+                self.history.seal();
+                self.add_kill(text, append, before)
+            }
+            None => self.status_message.set_message("No region to kill".into()),
+        }
+    }
+
+    /// Puts the region on the kill ring without deleting it
+    pub fn copy_region(&mut self, append: bool) {
+        match self.selection() {
+            Some((start, end)) => {
+                let text = self.editor_rows.get_text(start, end);
+                self.add_kill(text, append, false);
+                self.cursor_controller.anchor = None
+            }
+            None => self.status_message.set_message("No region to copy".into()),
+        }
+    }
+
+    pub fn yank(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        /* text copied or cut with the default commands counts as the newest kill */
+        if self.clipboard.is_some() && self.clipboard.as_ref() != self.kill_ring.entries.front() {
+            let text = self.clipboard.clone().unwrap();
+            self.kill_ring.kill(text, false, false)
+        }
+        let text = match self.kill_ring.entries.front() {
+            Some(text) => text.clone(),
+            None => {
+                self.status_message.set_message("Kill ring is empty".into());
+                return;
+            }
+        };
+        self.cursor_controller.anchor = None;
+        self.history.seal();
+        let start = self.cursor_controller.position();
+        self.insert_text(&text);
+        self.kill_ring.yank_index = 0;
+        self.kill_ring.last_yank = Some((start, self.cursor_controller.position()))
+    }
+
+    /// Replaces the text just yanked with the next older kill
+    pub fn yank_pop(&mut self) {
+        let (start, end) = match self.kill_ring.last_yank {
+            Some(range) if !self.kill_ring.entries.is_empty() => range,
+            _ => {
+                self.status_message
+                    .set_message("Previous command was not a yank".into());
+                return;
+            }
+        };
+        let kill_ring = &mut self.kill_ring;
+        kill_ring.yank_index = (kill_ring.yank_index + 1) % kill_ring.entries.len();
+        let text = kill_ring.entries[kill_ring.yank_index].clone();
+        self.cursor_controller.anchor = Some(start);
+        self.cursor_controller.set_position(end);
+        self.insert_text(&text);
+        self.kill_ring.last_yank = Some((start, self.cursor_controller.position()))
+    }
+}
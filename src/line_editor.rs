@@ -0,0 +1,352 @@
+use crate::*;
+
+/// Returns every way to finish what was typed into a prompt, best first
+pub type Completer = fn(&Output, &str) -> Vec<String>;
+
+/// Picks the field of `Output` that keeps the answers given to one kind of prompt
+pub type HistoryField = fn(&mut Output) -> &mut Vec<String>;
+
+/// Paths starting with `prefix`, with a `/` after directories. Hidden files are only
+/// offered once a `.` has been typed
+pub fn complete_path(_output: &Output, prefix: &str) -> Vec<String> {
+    let (directory, start) = match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() { "." } else { directory }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(start) || (name.starts_with('.') && !start.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", directory, name, slash))
+        })
+        .collect::<Vec<String>>();
+    paths.sort();
+    paths
+}
+
+/// File type names starting with `prefix`, along with `none` and `auto` for the syntax
+/// prompts
+pub fn complete_syntax(_output: &Output, prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    Output::syntax_list()
+        .iter()
+        .map(|it| it.file_type().to_lowercase())
+        .chain(["none".to_string(), "auto".to_string()])
+        .filter(|name| name.starts_with(&prefix))
+        .collect()
+}
+
+/// The input of a prompt, edited in place on the message bar
+pub struct LineEditor {
+    input: String,
+    /// Byte index into `input`
+    cursor: usize,
+    history: Option<HistoryField>,
+    /// The history entry on show and the input that was there before going through it
+    browsing: Option<(usize, String)>,
+    complete: Option<Completer>,
+    completions: Option<(Vec<String>, usize)>,
+    /// Leaves the arrow keys to the prompt's callback, which search uses to step through
+    /// matches
+    keep_arrows: bool,
+}
+
+impl LineEditor {
+    pub fn new(
+        history: Option<HistoryField>,
+        complete: Option<Completer>,
+        keep_arrows: bool,
+    ) -> Self {
+        Self {
+            input: String::with_capacity(32),
+            cursor: 0,
+            history,
+            browsing: None,
+            complete,
+            completions: None,
+            keep_arrows,
+        }
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Fills the `'\0'` in `template` with the input and a hint of the completions,
+    /// returning the message and the column of the cursor in it
+    pub fn render(&self, output: &Output, template: &str) -> (String, usize) {
+        let before = template.split('\0').next().unwrap_or_default();
+        let column = before.width() + self.input[..self.cursor].width();
+        let mut message = template.replacen('\0', &self.input, 1);
+        if let Some(complete) = self.complete {
+            let candidates = match &self.completions {
+                Some((candidates, _)) => candidates.clone(),
+                None => complete(output, &self.input),
+            };
+            if !candidates.is_empty() && candidates[0] != self.input {
+                /* only show what differs from the input, e.g. file names without their directory */
+                let cut = self.input.rfind([' ', '/']).map_or(0, |index| index + 1);
+                let shown = candidates[..cmp::min(candidates.len(), 6)]
+                    .iter()
+                    .map(
+                        |candidate| match candidate.starts_with(&self.input[..cut]) {
+                            true => &candidate[cut..],
+                            false => candidate.as_str(),
+                        },
+                    )
+                    .collect::<Vec<&str>>();
+                message.push_str(&format!("  [{}]", shown.join(" | ")));
+            }
+        }
+        (message, column)
+    }
+
+    /// Adds the input to the prompt's history, moving it to the end if it was there already
+    pub fn remember(&self, output: &mut Output) {
+        if let Some(history) = self.history {
+            let entries = history(output);
+            entries.retain(|entry| *entry != self.input);
+            entries.push(self.input.clone());
+        }
+    }
+
+    pub fn into_input(self) -> String {
+        self.input
+    }
+
+    fn set_input(&mut self, input: String) {
+        self.cursor = input.len();
+        self.input = input
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.input.insert_str(self.cursor, text);
+        self.cursor += text.len()
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.input[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.input[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |grapheme| self.cursor + grapheme.len())
+    }
+
+    /// Start of the word before the cursor, skipping the blanks in between as Ctrl-W does
+    fn word_start(&self) -> usize {
+        let before = self.input[..self.cursor].trim_end();
+        before.rfind(char::is_whitespace).map_or(0, |index| {
+            index + before[index..].chars().next().unwrap().len_utf8()
+        })
+    }
+
+    fn word_end(&self) -> usize {
+        let after = &self.input[self.cursor..];
+        let start = after.len() - after.trim_start().len();
+        after[start..]
+            .find(char::is_whitespace)
+            .map_or(self.input.len(), |index| self.cursor + start + index)
+    }
+
+    fn delete(&mut self, from: usize, to: usize) {
+        self.input.replace_range(from..to, "");
+        self.cursor = from
+    }
+
+    fn browse_history(&mut self, output: &mut Output, older: bool) {
+        let entries = match self.history {
+            Some(history) => history(output),
+            None => return,
+        };
+        let index = match (&self.browsing, older) {
+            (None, true) => entries.len().checked_sub(1),
+            (None, false) => None,
+            (Some((index, _)), true) => Some(index.saturating_sub(1)),
+            (Some((index, _)), false) => Some(index + 1).filter(|index| *index < entries.len()),
+        };
+        match index {
+            Some(index) => {
+                let entry = entries[index].clone();
+                let draft = match self.browsing.take() {
+                    Some((_, draft)) => draft,
+                    None => mem::take(&mut self.input),
+                };
+                self.browsing = Some((index, draft));
+                self.set_input(entry)
+            }
+            None => {
+                if let Some((_, draft)) = self.browsing.take() {
+                    self.set_input(draft)
+                }
+            }
+        }
+    }
+
+    fn cycle_completions(&mut self, output: &Output, forward: bool) {
+        let complete = match self.complete {
+            Some(complete) => complete,
+            None => return,
+        };
+        let input = &self.input;
+        let (candidates, index) = self.completions.get_or_insert_with(|| {
+            let candidates = complete(output, input);
+            let count = candidates.len();
+            /* the first Tab lands on the first candidate */
+            (
+                candidates,
+                if forward { count.saturating_sub(1) } else { 0 },
+            )
+        });
+        if candidates.is_empty() {
+            return;
+        }
+        *index = if forward {
+            (*index + 1) % candidates.len()
+        } else {
+            (*index + candidates.len() - 1) % candidates.len()
+        };
+        let candidate = candidates[*index].clone();
+        self.set_input(candidate)
+    }
+
+    /// Applies an editing key. Keys it has no use for are ignored
+    pub fn handle_key(&mut self, key: KeyEvent, output: &mut Output) {
+        let arrows = !self.keep_arrows;
+        let control = key.modifiers == KeyModifiers::CONTROL;
+        let alt = key.modifiers == KeyModifiers::ALT;
+        let plain = key.modifiers == KeyModifiers::NONE;
+        let edited = match key.code {
+            KeyCode::Left if arrows && plain => {
+                self.cursor = self.previous_boundary();
+                false
+            }
+            KeyCode::Right if arrows && plain => {
+                self.cursor = self.next_boundary();
+                false
+            }
+            KeyCode::Char('b') if control => {
+                self.cursor = self.previous_boundary();
+                false
+            }
+            KeyCode::Char('f') if control => {
+                self.cursor = self.next_boundary();
+                false
+            }
+            KeyCode::Left if arrows && control => {
+                self.cursor = self.word_start();
+                false
+            }
+            KeyCode::Char('b') if alt => {
+                self.cursor = self.word_start();
+                false
+            }
+            KeyCode::Right if arrows && control => {
+                self.cursor = self.word_end();
+                false
+            }
+            KeyCode::Char('f') if alt => {
+                self.cursor = self.word_end();
+                false
+            }
+            KeyCode::Home if plain => {
+                self.cursor = 0;
+                false
+            }
+            KeyCode::Char('a') if control => {
+                self.cursor = 0;
+                false
+            }
+            KeyCode::End if plain => {
+                self.cursor = self.input.len();
+                false
+            }
+            KeyCode::Char('e') if control => {
+                self.cursor = self.input.len();
+                false
+            }
+            KeyCode::Up if arrows && plain => {
+                self.browse_history(output, true);
+                false
+            }
+            KeyCode::Char('p') if control => {
+                self.browse_history(output, true);
+                false
+            }
+            KeyCode::Down if arrows && plain => {
+                self.browse_history(output, false);
+                false
+            }
+            KeyCode::Char('n') if control => {
+                self.browse_history(output, false);
+                false
+            }
+            KeyCode::Tab | KeyCode::BackTab if self.complete.is_some() => {
+                self.cycle_completions(output, key.code == KeyCode::Tab);
+                return;
+            }
+            KeyCode::Backspace if plain => {
+                self.delete(self.previous_boundary(), self.cursor);
+                true
+            }
+            KeyCode::Char('h') if control => {
+                self.delete(self.previous_boundary(), self.cursor);
+                true
+            }
+            KeyCode::Delete if plain => {
+                let end = self.next_boundary();
+                self.delete(self.cursor, end);
+                true
+            }
+            KeyCode::Char('d') if control => {
+                let end = self.next_boundary();
+                self.delete(self.cursor, end);
+                true
+            }
+            KeyCode::Char('w') if control => {
+                self.delete(self.word_start(), self.cursor);
+                true
+            }
+            KeyCode::Char('u') if control => {
+                self.delete(0, self.cursor);
+                true
+            }
+            KeyCode::Char('k') if control => {
+                self.input.truncate(self.cursor);
+                true
+            }
+            /* pastes the first line of the clipboard, since the input is a single line */
+            KeyCode::Char('v' | 'y') if control => {
+                let text = output.clipboard.clone().unwrap_or_default();
+                self.insert(text.lines().next().unwrap_or_default());
+                true
+            }
+            KeyCode::Tab if plain => {
+                self.insert("\t");
+                true
+            }
+            KeyCode::Char(ch) if plain || key.modifiers == KeyModifiers::SHIFT => {
+                self.insert(ch.encode_utf8(&mut [0; 4]));
+                true
+            }
+            _ => false,
+        };
+        if edited {
+            self.browsing = None
+        }
+        self.completions = None
+    }
+}
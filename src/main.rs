@@ -1,18 +1,49 @@
 /// Authored by Kofi Otuo <otuokofi@outlook.com>
 ///
+use crate::buffers::Buffer;
+use crate::cli::{Args, FileArg, Source};
+use crate::config::{Config, IndentStyle, Indentation};
+use crate::files::{FileFormat, LineEnding};
+use crate::history::{Edit, History};
+use crate::keymap::{describe_chord, Command, Keymap, Lookup};
+use crate::kill_ring::KillRing;
+use crate::line_editor::{complete_path, complete_syntax, LineEditor};
+use crate::rust::RustHighlight;
+use crate::syntax::Continuation;
+use crate::theme::{Style, Theme};
+use crate::vim::Vim;
+use crate::windows::{Layout, LineNumbers, Rect, View, Window};
 use crossterm::event::*;
 use crossterm::style::*;
 use crossterm::terminal::ClearType;
 use crossterm::{cursor, event, execute, queue, style, terminal};
+use regex::Regex;
 use std::cmp::Ordering;
-use std::io::{stdout, ErrorKind, Write};
-use std::path::PathBuf;
+use std::io::{stdout, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use std::{cmp, env, fs, io};
+use std::{cmp, env, fs, io, mem, process};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+mod buffers;
+mod cli;
+mod command_line;
+mod config;
+mod files;
+mod filetype;
+mod history;
+mod keymap;
+mod kill_ring;
+mod line_editor;
+mod palette;
+mod rust;
+mod syntax;
+mod theme;
+mod vim;
+mod windows;
 
 const VERSION: &str = "0.0.1";
-const TAB_STOP: usize = 8;
-const QUIT_TIMES: u8 = 3;
 
 struct CleanUp;
 
@@ -26,58 +57,73 @@ impl Drop for CleanUp {
 #[macro_export]
 macro_rules! prompt {
     ($output:expr,$args:tt) => {
-        prompt!($output, $args, callback = |&_, _, _| {})
+        prompt!(@read $output, $args, |&_, _, _| {}, false, None, None, false)
     };
-    ($output:expr,$args:tt, callback = $callback:expr) => {{
+    ($output:expr,$args:tt, allow_empty) => {
+        prompt!(@read $output, $args, |&_, _, _| {}, true, None, None, false)
+    };
+    /* a callback gets the arrow keys, as searching moves between matches with them */
+    ($output:expr,$args:tt, callback = $callback:expr) => {
+        prompt!(@read $output, $args, $callback, false, None, None, true)
+    };
+    /* `history` names the `Vec<String>` field of `Output` that Up and Down go through;
+    Tab cycles through what `complete` returns for the input, which is also shown as a hint */
+    ($output:expr,$args:tt, callback = $callback:expr, history = $history:ident) => {
+        prompt!(@read $output, $args, $callback, false,
+            Some(|output: &mut Output| &mut output.$history), None, true)
+    };
+    ($output:expr,$args:tt, history = $history:ident) => {
+        prompt!(@read $output, $args, |&_, _, _| {}, false,
+            Some(|output: &mut Output| &mut output.$history), None, false)
+    };
+    ($output:expr,$args:tt, complete = $complete:expr) => {
+        prompt!(@read $output, $args, |&_, _, _| {}, false, None, Some($complete), false)
+    };
+    ($output:expr,$args:tt, history = $history:ident, complete = $complete:expr) => {
+        prompt!(@read $output, $args, |&_, _, _| {}, false,
+            Some(|output: &mut Output| &mut output.$history), Some($complete), false)
+    };
+    (@read $output:expr,$args:tt, $callback:expr, $allow_empty:expr, $history:expr, $complete:expr, $keep_arrows:expr) => {{
         let output: &mut Output = $output;
-        let mut input = String::with_capacity(32);
+        let mut line = LineEditor::new($history, $complete, $keep_arrows);
+        let mut accepted = false;
         loop {
-            output.status_message.set_message(format!($args, input));
+            let (message, column) = line.render(output, &format!($args, '\0'));
+            output.status_message.set_message(message);
+            output.prompt_cursor = Some(column);
             output.refresh_screen()?;
-            let key_event = Reader.read_key()?;
+            let key_event = Reader.read_key(output)?;
             match key_event {
                 KeyEvent {
                     code: KeyCode::Enter,
                     modifiers: KeyModifiers::NONE,
-                } => {
-                    if !input.is_empty() {
-                        output.status_message.set_message(String::new());
-                        $callback(output, &input, KeyCode::Enter);
-                        break;
-                    }
-                }
-                KeyEvent {
-                    code: KeyCode::Esc, ..
-                } => {
+                } if !line.input().is_empty() || $allow_empty => {
                     output.status_message.set_message(String::new());
-                    input.clear();
-                    $callback(output, &input, KeyCode::Esc);
+                    line.remember(output);
+                    $callback(output, line.input(), KeyCode::Enter);
+                    accepted = true;
                     break;
                 }
                 KeyEvent {
-                    code: KeyCode::Backspace | KeyCode::Delete,
-                    modifiers: KeyModifiers::NONE,
-                } => {
-                    input.pop();
+                    code: KeyCode::Esc, ..
                 }
-                KeyEvent {
-                    code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                | KeyEvent {
+                    code: KeyCode::Char('g'),
+                    modifiers: KeyModifiers::CONTROL,
                 } => {
-                    input.push(match code {
-                        KeyCode::Tab => '\t',
-                        KeyCode::Char(ch) => ch,
-                        _ => unreachable!(),
-                    });
+                    output.status_message.set_message(String::new());
+                    $callback(output, "", KeyCode::Esc);
+                    break;
                 }
-                _ => {}
+                _ => line.handle_key(key_event, output),
             }
-            $callback(output, &input, key_event.code);
+            $callback(output, line.input(), key_event.code);
         }
-        if input.is_empty() {
-            None
+        output.prompt_cursor = None;
+        if accepted {
+            Some(line.into_input())
         } else {
-            Some(input)
+            None
         }
     }};
 }
@@ -91,30 +137,61 @@ enum HighlightType {
     CharLiteral,
     Comment,
     MultilineComment, // add line
+    DocComment,
+    Keyword,
+    Type,
+    Function,
+    Constant,
+    Attribute,
+    Variable,
+    Operator,
+    Punctuation,
+    Error,
+    Strong,
+    Emphasis,
     Other(Color),
 }
 
 trait SyntaxHighlight {
     fn extensions(&self) -> &[&str];
+    /// Other names for the file type, as modelines and `:set ft=` may give it
+    fn aliases(&self) -> &[&str] {
+        &[]
+    }
+    /// Whole file names, such as `Makefile`, for files picked out by more than an extension
+    fn filenames(&self) -> &[&str] {
+        &[]
+    }
+    /// Patterns such as `Dockerfile.*`, matched against the whole path when they hold a `/`
+    fn globs(&self) -> &[&str] {
+        &[]
+    }
+    /// Interpreters that a `#!` first line may name
+    fn shebangs(&self) -> &[&str] {
+        &[]
+    }
     fn file_type(&self) -> &str;
-    fn comment_start(&self) -> &str;
-    fn multiline_comment(&self) -> Option<(&str, &str)>; // add line
-    fn syntax_color(&self, highlight_type: &HighlightType) -> Color;
     fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>);
-    fn color_row(&self, render: &str, highlight: &[HighlightType], out: &mut EditorContents) {
-        let mut current_color = self.syntax_color(&HighlightType::Normal);
+    fn color_row(
+        &self,
+        render: &str,
+        highlight: &[HighlightType],
+        theme: &Theme,
+        out: &mut EditorContents,
+    ) {
+        let mut current_style = Style::default();
         render.char_indices().for_each(|(i, c)| {
-            let color = self.syntax_color(&highlight[i]);
-            if current_color != color {
-                current_color = color;
-                let _ = queue!(out, SetForegroundColor(color));
+            let style = theme.style(&highlight[i]);
+            if current_style != style {
+                let _ = style.queue_from(&current_style, theme.depth, out);
+                current_style = style;
             }
             out.push(c);
         });
-        let _ = queue!(out, SetForegroundColor(Color::Reset));
+        let _ = Style::default().queue_from(&current_style, theme.depth, out);
     }
     fn is_separator(&self, c: char) -> bool {
-        c.is_whitespace()
+        c.is_ascii_whitespace()
             || [
                 ',', '.', '[', ']', '(', ')', '+', '-', '/', '*', '=', '~', '%', '<', '>', '"',
                 '\'', ';', '&',
@@ -123,217 +200,18 @@ trait SyntaxHighlight {
     }
 }
 
-syntax_struct! {
-    struct RustHighlight {
-        extensions:["rs"],
-        file_type:"rust",
-        comment_start:"//",
-        keywords : {
-            [Color::Yellow;
-                "mod","unsafe","extern","crate","use","type","struct","enum","union","const","static",
-                "mut","let","if","else","impl","trait","for","fn","self","Self", "while", "true","false",
-                "in","continue","break","loop","match"
-            ],
-            [Color::Magenta; "isize","i8","i16","i32","i64","usize","u8","u16","u32","u64","f32","f64",
-                "char","str","bool"
-            ]
-        },
-        multiline_comment: Some(("/*", "*/"))
-    }
-}
-
-#[macro_export]
-macro_rules! syntax_struct {
-    (
-        struct $Name:ident {
-            extensions:$ext:expr,
-            file_type:$type:expr,
-            comment_start:$start:expr,
-            keywords: {
-                $([$color:expr; $($words:expr),*]),*
-            },
-            multiline_comment:$ml_comment:expr
-        }
-    ) => {
-        struct $Name {
-            extensions: &'static [&'static str],
-            file_type: &'static str,
-            comment_start:&'static str,
-            multiline_comment:Option<(&'static str,&'static str)>
-        }
-
-        impl $Name {
-            fn new() -> Self {
-                Self {
-                    extensions: &$ext,
-                    file_type: $type,
-                    comment_start:$start,
-                    multiline_comment: $ml_comment
-                }
-            }
-        }
-
-        impl SyntaxHighlight for $Name {
-
-            fn comment_start(&self) -> &str {
-                self.comment_start
-            }
-
-            fn multiline_comment(&self) -> Option<(&str, &str)> {
-                self.multiline_comment
-            }
-
-            fn extensions(&self) -> &[&str] {
-                self.extensions
-            }
-
-            fn file_type(&self) -> &str {
-                self.file_type
-            }
-
-            fn syntax_color(&self, highlight_type: &HighlightType) -> Color {
-                match highlight_type {
-                    HighlightType::Normal => Color::Reset,
-                    HighlightType::Number => Color::Cyan,
-                    HighlightType::SearchMatch => Color::Blue,
-                    HighlightType::String => Color::Green,
-                    HighlightType::CharLiteral => Color::DarkGreen,
-                    HighlightType::Comment | HighlightType::MultilineComment => Color::DarkGrey,
-                    HighlightType::Other(color) => *color
-                }
-            }
-
-            fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>) {
-                let mut in_comment = at > 0 && editor_rows[at - 1].is_comment; // add line
-                let current_row = &mut editor_rows[at];
-                macro_rules! add {
-                    ($h:expr) => {
-                        current_row.highlight.push($h)
-                    };
-                }
-                current_row.highlight = Vec::with_capacity(current_row.render.len());
-                let render = current_row.render.as_bytes();
-                let mut i = 0;
-                let mut previous_separator = true;
-                let mut in_string: Option<char> = None;
-                let comment_start = self.comment_start().as_bytes();
-                while i < render.len() {
-                    let c = render[i] as char;
-                    let previous_highlight = if i > 0 {
-                        current_row.highlight[i - 1]
-                    } else {
-                        HighlightType::Normal
-                    };
-                    if in_string.is_none() && !comment_start.is_empty() && !in_comment { // modify
-                        let end = i + comment_start.len();
-                        if render[i..cmp::min(end, render.len())] == *comment_start {
-                            (i..render.len()).for_each(|_| add!(HighlightType::Comment));
-                            break;
-                        }
-                    }
-                    if let Some(val) = $ml_comment {
-                        if in_string.is_none() {
-                            if in_comment {
-                                add!(HighlightType::MultilineComment);
-                                let end = i + val.1.len();
-                                if render[i..cmp::min(render.len(),end)] == *val.1.as_bytes() {
-                                    (0..val.1.len().saturating_sub(1)).for_each(|_| add!(HighlightType::MultilineComment));
-                                    i = end;
-                                    previous_separator = true;
-                                    in_comment = false;
-                                    continue
-                                } else {
-                                    i+=1;
-                                    continue
-                                }
-                            } else {
-                                let end = i + val.0.len();
-                                if render[i..cmp::min(render.len(),end)] == *val.0.as_bytes() {
-                                    (i..end).for_each(|_| add!(HighlightType::MultilineComment));
-                                    i+= val.0.len();
-                                    in_comment = true;
-                                    continue
-                                }
-                            }
-                        }
-                    }
-                    if let Some(val) = in_string {
-                        add! {
-                            if val == '"' { HighlightType::String } else { HighlightType::CharLiteral }
-                        }
-                        if c == '\\' && i + 1 < render.len() {
-                            add! {
-                                if val == '"' { HighlightType::String } else { HighlightType::CharLiteral }
-                            }
-                            i += 2;
-                            continue
-                        }
-                        if val == c {
-                            in_string = None;
-                        }
-                        i += 1;
-                        previous_separator = true;
-                        continue;
-                    } else if c == '"' || c == '\'' {
-                        in_string = Some(c);
-                        add! {
-                            if c == '"' { HighlightType::String } else { HighlightType::CharLiteral }
-                        }
-                        i += 1;
-                        continue;
-                    }
-                    if (c.is_digit(10)
-                        && (previous_separator
-                            || matches!(previous_highlight, HighlightType::Number)))
-                        || (c == '.' && matches!(previous_highlight, HighlightType::Number))
-                    {
-                        add!(HighlightType::Number);
-                        i += 1;
-                        previous_separator = false;
-                        continue;
-                    }
-                    if previous_separator {
-                        $(
-                            $(
-                                let end = i + $words.len();
-                                let is_end_or_sep = render
-                                    .get(end)
-                                    .map(|c| self.is_separator(*c as char))
-                                    .unwrap_or(end == render.len());
-                                if is_end_or_sep && render[i..end] == *$words.as_bytes() {
-                                    (i..end).for_each(|_| add!(HighlightType::Other($color)));
-                                    i += $words.len();
-                                    previous_separator = false;
-                                    continue;
-                                }
-                            )*
-                        )*
-                    }
-                    add!(HighlightType::Normal);
-                    previous_separator = self.is_separator(c);
-                    i += 1;
-                }
-                assert_eq!(current_row.render.len(), current_row.highlight.len());
-                let changed = current_row.is_comment != in_comment;
-                current_row.is_comment = in_comment;
-                if (changed && at + 1 < editor_rows.len()) {
-                    self.update_syntax(at+1,editor_rows)
-                }
-            }
-        }
-    };
-}
-
 struct StatusMessage {
     message: Option<String>,
     set_time: Option<Instant>,
+    timeout: Duration,
 }
 
 impl StatusMessage {
-    fn new(initial_message: String) -> Self {
+    fn new(initial_message: String, timeout: Duration) -> Self {
         Self {
             message: Some(initial_message),
             set_time: Some(Instant::now()),
+            timeout,
         }
     }
 
@@ -344,7 +222,7 @@ impl StatusMessage {
 
     fn message(&mut self) -> Option<&String> {
         self.set_time.and_then(|time| {
-            if time.elapsed() > Duration::from_secs(5) {
+            if time.elapsed() > self.timeout {
                 self.message = None;
                 self.set_time = None;
                 None
@@ -360,76 +238,236 @@ struct Row {
     render: String,
     highlight: Vec<HighlightType>,
     is_comment: bool, // add line
+    /// A string the row leaves open for the next one, such as a `"""` string or a heredoc
+    continuation: Option<Continuation>,
+    tab_stop: usize,
 }
 
 impl Row {
-    fn new(row_content: String, render: String) -> Self {
+    fn new(row_content: String, render: String, tab_stop: usize) -> Self {
         Self {
             row_content,
             render,
             highlight: Vec::new(),
             is_comment: false, // add line
+            continuation: None,
+            tab_stop,
         }
     }
 
-    fn insert_char(&mut self, at: usize, ch: char) {
-        self.row_content.insert(at, ch);
-        EditorRows::render_row(self)
+    /// Inserts `ch` before the grapheme at `at` and returns the grapheme index just past it
+    fn insert_char(&mut self, at: usize, ch: char) -> usize {
+        let index = self.byte_index(at);
+        self.row_content.insert(index, ch);
+        EditorRows::render_row(self);
+        self.grapheme_index(index + ch.len_utf8())
     }
 
-    fn delete_char(&mut self, at: usize) {
-        self.row_content.remove(at);
-        EditorRows::render_row(self)
+    fn delete_char(&mut self, at: usize) -> String {
+        let (start, end) = (self.byte_index(at), self.byte_index(at + 1));
+        let removed = self.row_content.drain(start..end).collect();
+        EditorRows::render_row(self);
+        removed
     }
 
-    fn get_row_content_x(&self, render_x: usize) -> usize {
-        let mut current_render_x = 0;
-        for (cursor_x, ch) in self.row_content.chars().enumerate() {
-            if ch == '\t' {
-                current_render_x += (TAB_STOP - 1) - (current_render_x % TAB_STOP);
-            }
-            current_render_x += 1;
-            if current_render_x > render_x {
-                return cursor_x;
-            }
+    /// Number of grapheme clusters, which is what `cursor_x` counts
+    fn len(&self) -> usize {
+        self.row_content.graphemes(true).count()
+    }
+
+    /// Byte offset into `row_content` of the grapheme at `at`
+    fn byte_index(&self, at: usize) -> usize {
+        self.row_content
+            .grapheme_indices(true)
+            .nth(at)
+            .map(|(index, _)| index)
+            .unwrap_or(self.row_content.len())
+    }
+
+    /// Grapheme index of the cluster containing byte `index` of `row_content`
+    fn grapheme_index(&self, index: usize) -> usize {
+        self.row_content
+            .grapheme_indices(true)
+            .take_while(|(start, _)| *start < index)
+            .count()
+    }
+
+    /// Yields the render column, render byte offset and display width of each grapheme
+    fn render_positions(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        let tab_stop = self.tab_stop;
+        self.row_content
+            .graphemes(true)
+            .scan((0, 0), move |(render_x, render_index), grapheme| {
+                let (width, len) = if grapheme == "\t" {
+                    let width = tab_stop - (*render_x % tab_stop);
+                    (width, width)
+                } else if grapheme == "\r" {
+                    (2, 2)
+                } else {
+                    (grapheme.width(), grapheme.len())
+                };
+                let position = (*render_x, *render_index, width);
+                *render_x += width;
+                *render_index += len;
+                Some(position)
+            })
+    }
+
+    fn get_render_x(&self, cursor_x: usize) -> usize {
+        match self.render_positions().nth(cursor_x) {
+            Some((render_x, _, _)) => render_x,
+            None => self.render.width(),
+        }
+    }
+
+    /// Byte offset into `render` (and `highlight`) of the grapheme at `cursor_x`
+    fn get_render_index(&self, cursor_x: usize) -> usize {
+        match self.render_positions().nth(cursor_x) {
+            Some((_, render_index, _)) => render_index,
+            None => self.render.len(),
         }
-        0
+    }
+
+    fn get_row_content_x(&self, render_x: usize) -> usize {
+        self.render_positions()
+            .position(|(current_render_x, _, width)| current_render_x + width > render_x)
+            .unwrap_or_else(|| self.len())
     }
 }
 
 struct EditorRows {
     row_contents: Vec<Row>,
     filename: Option<PathBuf>,
+    format: FileFormat,
+    indentation: Indentation,
+    read_only: bool,
+    /// Set when the file wasn't valid UTF-8 text and was decoded with replacements
+    lossy: bool,
 }
 
 impl EditorRows {
-    fn new(syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
-        match env::args().nth(1) {
-            None => Self {
-                row_contents: Vec::new(),
-                filename: None,
-            },
-            Some(file) => Self::from_file(file.into(), syntax_highlight),
+    fn new() -> Self {
+        Self {
+            row_contents: Vec::new(),
+            filename: None,
+            format: FileFormat::new(),
+            indentation: Indentation::default(),
+            read_only: false,
+            lossy: false,
         }
     }
 
-    fn from_file(file: PathBuf, syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>) -> Self {
-        let file_contents = fs::read_to_string(&file).expect("Unable to read file");
-        let mut row_contents = Vec::new();
-        file.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| Output::select_syntax(ext).map(|syntax| syntax_highlight.insert(syntax)));
-        file_contents.lines().enumerate().for_each(|(i, line)| {
-            let mut row = Row::new(line.into(), String::new());
+    fn display_name(&self) -> &str {
+        self.filename
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("[No Name]")
+    }
+
+    /// Loads `file`, returning a note for the status bar when it wasn't opened as plain text.
+    /// A file that doesn't exist yet gives an empty buffer that saves to that name. The
+    /// highlighter is worked out from the file unless one was chosen already
+    fn from_file(
+        file: PathBuf,
+        syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>,
+    ) -> io::Result<(Self, Option<String>)> {
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if syntax_highlight.is_none() {
+                    *syntax_highlight = Output::syntax_for_path(&file);
+                }
+                let notice = format!("New file: {}", file.display());
+                let mut editor_rows = Self::new();
+                editor_rows.filename = Some(file);
+                return Ok((editor_rows, Some(notice)));
+            }
+            Err(err) => return Err(err),
+        };
+        let (mut editor_rows, notice) = Self::from_bytes(
+            &bytes,
+            &file.display().to_string(),
+            Some(&file),
+            syntax_highlight,
+        );
+        editor_rows.filename = Some(file);
+        Ok((editor_rows, notice))
+    }
+
+    /// Builds an unnamed buffer from raw file contents; `source` names them in the note
+    /// returned when they aren't UTF-8 text, and `path` helps pick the highlighter
+    fn from_bytes(
+        bytes: &[u8],
+        source: &str,
+        path: Option<&Path>,
+        syntax_highlight: &mut Option<Box<dyn SyntaxHighlight>>,
+    ) -> (Self, Option<String>) {
+        let mut editor_rows = Self::new();
+        let (file_contents, notice) = match files::decode(bytes) {
+            Some(text) => (text.to_string(), None),
+            None => {
+                editor_rows.read_only = true;
+                editor_rows.lossy = true;
+                let notice = format!(
+                    "{} is binary or not UTF-8; showing a read-only lossy view",
+                    source
+                );
+                (files::decode_lossy(bytes), Some(notice))
+            }
+        };
+        if syntax_highlight.is_none() {
+            *syntax_highlight = Output::detect_syntax(path, &file_contents);
+        }
+        let (format, lines) = FileFormat::parse(&file_contents);
+        lines.into_iter().enumerate().for_each(|(i, line)| {
+            let mut row = Row::new(
+                line.into(),
+                String::new(),
+                editor_rows.indentation.tab_width,
+            );
             Self::render_row(&mut row);
-            row_contents.push(row);
+            editor_rows.row_contents.push(row);
             if let Some(it) = syntax_highlight {
-                it.update_syntax(i, &mut row_contents)
+                it.update_syntax(i, &mut editor_rows.row_contents)
             }
         });
-        Self {
-            filename: Some(file),
-            row_contents,
+        editor_rows.format = format;
+        let notice = notice.or_else(|| {
+            format.mixed.then(|| {
+                format!(
+                    "{} mixes LF and CRLF line endings; they are kept until converted",
+                    source
+                )
+            })
+        });
+        (editor_rows, notice)
+    }
+
+    /// Applies the settings for this buffer's file type, re-rendering and re-highlighting
+    /// every row if the tab width changed
+    fn configure(&mut self, config: &Config, syntax_highlight: Option<&dyn SyntaxHighlight>) {
+        let indentation = config.indentation(syntax_highlight.map(|it| it.file_type()));
+        self.set_indentation(indentation, syntax_highlight)
+    }
+
+    fn set_indentation(
+        &mut self,
+        indentation: Indentation,
+        syntax_highlight: Option<&dyn SyntaxHighlight>,
+    ) {
+        let tab_width_changed = indentation.tab_width != self.indentation.tab_width;
+        self.indentation = indentation;
+        if !tab_width_changed {
+            return;
+        }
+        for at in 0..self.number_of_rows() {
+            let row = self.get_editor_row_mut(at);
+            row.tab_stop = indentation.tab_width;
+            Self::render_row(row);
+            if let Some(it) = syntax_highlight {
+                it.update_syntax(at, &mut self.row_contents)
+            }
         }
     }
 
@@ -441,10 +479,6 @@ impl EditorRows {
         &self.row_contents[at].row_content
     }
 
-    fn get_render(&self, at: usize) -> &String {
-        &self.row_contents[at].render
-    }
-
     fn get_editor_row(&self, at: usize) -> &Row {
         &self.row_contents[at]
     }
@@ -454,56 +488,132 @@ impl EditorRows {
     }
 
     fn render_row(row: &mut Row) {
-        let mut index = 0;
-        let capacity = row
-            .row_content
-            .chars()
-            .fold(0, |acc, next| acc + if next == '\t' { TAB_STOP } else { 1 });
+        let mut render_x = 0;
+        let capacity = row.row_content.chars().fold(0, |acc, next| {
+            acc + if next == '\t' {
+                row.tab_stop
+            } else {
+                next.len_utf8()
+            }
+        });
         row.render = String::with_capacity(capacity);
-        row.row_content.chars().for_each(|c| {
-            index += 1;
-            if c == '\t' {
+        row.row_content.graphemes(true).for_each(|grapheme| {
+            if grapheme == "\t" {
                 row.render.push(' ');
-                while index % TAB_STOP != 0 {
+                render_x += 1;
+                while render_x % row.tab_stop != 0 {
                     row.render.push(' ');
-                    index += 1
+                    render_x += 1
                 }
+            } else if grapheme == "\r" {
+                /* the CR of a CRLF line in a file with mixed line endings, shown as vim does */
+                row.render.push_str("^M");
+                render_x += 2
             } else {
-                row.render.push(c);
+                row.render.push_str(grapheme);
+                render_x += grapheme.width();
             }
         });
     }
 
     fn insert_row(&mut self, at: usize, contents: String) {
-        let mut new_row = Row::new(contents, String::new());
+        let mut new_row = Row::new(contents, String::new(), self.indentation.tab_width);
         EditorRows::render_row(&mut new_row);
         self.row_contents.insert(at, new_row);
     }
 
     fn save(&mut self) -> io::Result<usize> {
         match &self.filename {
-            None => Err(io::Error::new(ErrorKind::Other, "no file name specified")),
-            Some(name) => {
-                let mut file = fs::OpenOptions::new().write(true).create(true).open(name)?;
-                let contents: String = self
-                    .row_contents
-                    .iter()
-                    .map(|it| it.row_content.as_str())
-                    .collect::<Vec<&str>>()
-                    .join("\n");
-                file.set_len(contents.len() as u64)?;
-                file.write_all(contents.as_bytes())?;
-                Ok(contents.as_bytes().len())
-            }
+            None => Err(io::Error::other("no file name specified")),
+            Some(name) => self.save_to(name),
         }
     }
 
+    /// Writes the rows to `path`, which need not be the buffer's own file
+    fn save_to(&self, path: &Path) -> io::Result<usize> {
+        let contents = self
+            .format
+            .serialize(self.row_contents.iter().map(|it| it.row_content.as_str()));
+        files::write_atomic(path, contents.as_bytes())?;
+        Ok(contents.len())
+    }
+
     fn join_adjacent_rows(&mut self, at: usize) {
         let current_row = self.row_contents.remove(at);
         let previous_row = self.get_editor_row_mut(at - 1);
         previous_row.row_content.push_str(&current_row.row_content);
         Self::render_row(previous_row);
     }
+
+    fn insert_text(&mut self, at: Position, text: &str) -> Position {
+        if at.y == self.number_of_rows() {
+            self.insert_row(at.y, String::new())
+        }
+        let row = self.get_editor_row_mut(at.y);
+        let tail = row.row_content.split_off(row.byte_index(at.x));
+        let mut lines = text.split('\n');
+        row.row_content.push_str(lines.next().unwrap_or_default());
+        let mut end = Position {
+            x: row.row_content.len(),
+            y: at.y,
+        };
+        for line in lines {
+            end = Position {
+                x: line.len(),
+                y: end.y + 1,
+            };
+            self.insert_row(end.y, line.into());
+        }
+        let last_row = self.get_editor_row_mut(end.y);
+        last_row.row_content.push_str(&tail);
+        end.x = last_row.grapheme_index(end.x);
+        (at.y..=end.y).for_each(|at| Self::render_row(self.get_editor_row_mut(at)));
+        end
+    }
+
+    fn get_text(&self, start: Position, end: Position) -> String {
+        let (start_row, end_row) = (self.get_editor_row(start.y), self.get_editor_row(end.y));
+        let (start_index, end_index) = (start_row.byte_index(start.x), end_row.byte_index(end.x));
+        if start.y == end.y {
+            return start_row.row_content[start_index..end_index].into();
+        }
+        let mut text = String::from(&start_row.row_content[start_index..]);
+        for at in start.y + 1..end.y {
+            text.push('\n');
+            text.push_str(self.get_row(at));
+        }
+        text.push('\n');
+        text.push_str(&end_row.row_content[..end_index]);
+        text
+    }
+
+    fn delete_text(&mut self, start: Position, end: Position) -> String {
+        let removed = self.get_text(start, end);
+        let start_index = self.get_editor_row(start.y).byte_index(start.x);
+        let end_index = self.get_editor_row(end.y).byte_index(end.x);
+        if start.y == end.y {
+            self.get_editor_row_mut(start.y)
+                .row_content
+                .drain(start_index..end_index);
+        } else {
+            self.get_editor_row_mut(start.y)
+                .row_content
+                .truncate(start_index);
+            self.row_contents.drain(start.y + 1..end.y);
+            self.get_editor_row_mut(start.y + 1)
+                .row_content
+                .drain(..end_index);
+            self.join_adjacent_rows(start.y + 1);
+        }
+        Self::render_row(self.get_editor_row_mut(start.y));
+        removed
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Position {
+    y: usize,
+    x: usize,
 }
 
 #[derive(Copy, Clone)]
@@ -515,6 +625,7 @@ struct CursorController {
     row_offset: usize,
     column_offset: usize,
     render_x: usize,
+    anchor: Option<Position>,
 }
 
 impl CursorController {
@@ -527,20 +638,86 @@ impl CursorController {
             row_offset: 0,
             column_offset: 0,
             render_x: 0,
+            anchor: None,
+        }
+    }
+
+    /// Drops the selection, or starts one at the cursor if `selecting` and none exists
+    fn update_anchor(&mut self, selecting: bool) {
+        if !selecting {
+            self.anchor = None
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.position())
         }
     }
 
     fn get_render_x(&self, row: &Row) -> usize {
-        row.row_content
-            .chars()
-            .take(self.cursor_x)
-            .fold(0, |render_x, c| {
-                if c == '\t' {
-                    render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
-                } else {
-                    render_x + 1
+        row.get_render_x(self.cursor_x)
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            x: self.cursor_x,
+            y: self.cursor_y,
+        }
+    }
+
+    fn set_position(&mut self, position: Position) {
+        self.cursor_x = position.x;
+        self.cursor_y = position.y;
+    }
+
+    /// The selected range ordered as (start, end), with both ends inside the buffer
+    fn selection(&self, editor_rows: &EditorRows) -> Option<(Position, Position)> {
+        let anchor = self.anchor?;
+        let clamp = |position: Position| {
+            let last_row = editor_rows.number_of_rows().checked_sub(1)?;
+            Some(if position.y > last_row {
+                Position {
+                    x: editor_rows.get_editor_row(last_row).len(),
+                    y: last_row,
                 }
+            } else {
+                position
             })
+        };
+        let (anchor, cursor) = (clamp(anchor)?, clamp(self.position())?);
+        match anchor.cmp(&cursor) {
+            Ordering::Less => Some((anchor, cursor)),
+            Ordering::Greater => Some((cursor, anchor)),
+            Ordering::Equal => None,
+        }
+    }
+
+    /// Byte range of the render of row `at` covered by the selection
+    fn selected_range(&self, editor_rows: &EditorRows, at: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.selection(editor_rows)?;
+        if at < start.y || at > end.y {
+            return None;
+        }
+        let row = editor_rows.get_editor_row(at);
+        let from = if at == start.y {
+            row.get_render_index(start.x)
+        } else {
+            0
+        };
+        let to = if at == end.y {
+            row.get_render_index(end.x)
+        } else {
+            row.render.len()
+        };
+        Some((from, to))
+    }
+
+    /// Pulls the cursor back inside the buffer after it was edited through another view
+    fn clamp(&mut self, editor_rows: &EditorRows) {
+        self.cursor_y = cmp::min(self.cursor_y, editor_rows.number_of_rows());
+        let row_len = if self.cursor_y < editor_rows.number_of_rows() {
+            editor_rows.get_editor_row(self.cursor_y).len()
+        } else {
+            0
+        };
+        self.cursor_x = cmp::min(self.cursor_x, row_len);
     }
 
     fn scroll(&mut self, editor_rows: &EditorRows) {
@@ -558,6 +735,18 @@ impl CursorController {
         }
     }
 
+    /// Jumps to `at`, clamped to the buffer, with its row in the middle of the screen
+    fn go_to(&mut self, at: Position, editor_rows: &EditorRows) {
+        self.anchor = None;
+        self.cursor_y = cmp::min(at.y, editor_rows.number_of_rows().saturating_sub(1));
+        self.cursor_x = 0;
+        if self.cursor_y < editor_rows.number_of_rows() {
+            self.cursor_x = cmp::min(at.x, editor_rows.get_editor_row(self.cursor_y).len());
+        }
+        self.row_offset = self.cursor_y.saturating_sub(self.screen_rows / 2);
+        self.scroll(editor_rows);
+    }
+
     fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
         let number_of_rows = editor_rows.number_of_rows();
 
@@ -570,7 +759,7 @@ impl CursorController {
                     self.cursor_x -= 1;
                 } else if self.cursor_y > 0 {
                     self.cursor_y -= 1;
-                    self.cursor_x = editor_rows.get_row(self.cursor_y).len();
+                    self.cursor_x = editor_rows.get_editor_row(self.cursor_y).len();
                 }
             }
             KeyCode::Down => {
@@ -580,7 +769,10 @@ impl CursorController {
             }
             KeyCode::Right => {
                 if self.cursor_y < number_of_rows {
-                    match self.cursor_x.cmp(&editor_rows.get_row(self.cursor_y).len()) {
+                    match self
+                        .cursor_x
+                        .cmp(&editor_rows.get_editor_row(self.cursor_y).len())
+                    {
                         Ordering::Less => self.cursor_x += 1,
                         Ordering::Equal => {
                             self.cursor_y += 1;
@@ -592,14 +784,14 @@ impl CursorController {
             }
             KeyCode::End => {
                 if self.cursor_y < number_of_rows {
-                    self.cursor_x = editor_rows.get_row(self.cursor_y).len();
+                    self.cursor_x = editor_rows.get_editor_row(self.cursor_y).len();
                 }
             }
             KeyCode::Home => self.cursor_x = 0,
             _ => unimplemented!(),
         }
         let row_len = if self.cursor_y < number_of_rows {
-            editor_rows.get_row(self.cursor_y).len()
+            editor_rows.get_editor_row(self.cursor_y).len()
         } else {
             0
         };
@@ -607,6 +799,47 @@ impl CursorController {
     }
 }
 
+/// Longest prefix of `text` that fits in `width` terminal columns
+fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut current_width = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        current_width += grapheme.width();
+        if current_width > width {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// Parses a location typed by the user: `line`, `line:col`, `+N`/`-N` lines from `current`
+/// or a percentage of the file. Lines and columns are 1-based
+fn parse_location(spec: &str, current: usize, number_of_rows: usize) -> Option<Position> {
+    let spec = spec.trim();
+    if let Some(percent) = spec.strip_suffix('%') {
+        let percent = cmp::min(percent.trim().parse::<usize>().ok()?, 100);
+        let y = (number_of_rows * percent / 100).saturating_sub(if percent == 0 { 0 } else { 1 });
+        return Some(Position { y, x: 0 });
+    }
+    if let Some(offset) = spec.strip_prefix('+') {
+        let y = current + offset.parse::<usize>().ok()?;
+        return Some(Position { y, x: 0 });
+    }
+    if let Some(offset) = spec.strip_prefix('-') {
+        let y = current.saturating_sub(offset.parse().ok()?);
+        return Some(Position { y, x: 0 });
+    }
+    let mut parts = spec.splitn(2, ':');
+    let line: usize = parts.next()?.parse().ok()?;
+    let column: usize = match parts.next() {
+        Some(column) => column.parse().ok()?,
+        None => 1,
+    };
+    Some(Position {
+        y: line.saturating_sub(1),
+        x: column.saturating_sub(1),
+    })
+}
+
 struct EditorContents {
     content: String,
 }
@@ -688,32 +921,196 @@ struct Output {
     dirty: u64,
     search_index: SearchIndex,
     syntax_highlight: Option<Box<dyn SyntaxHighlight>>,
+    history: History,
+    clipboard: Option<String>,
+    kill_ring: KillRing,
+    search_history: Vec<String>,
+    file_history: Vec<String>,
+    command_history: Vec<String>,
+    location_history: Vec<String>,
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    windows: Vec<Window>,
+    current_window: usize,
+    layout: Layout,
+    line_numbers: LineNumbers,
+    config: Config,
+    /// Shown at the left of the focused window's status bar, e.g. the vim mode
+    mode_indicator: Option<String>,
+    /// Where the cursor goes on the message bar while a prompt is being answered
+    prompt_cursor: Option<usize>,
 }
 
 impl Output {
+    /// Every highlighter, the ones defined in the config directory first so that they can
+    /// replace the built in ones
+    fn syntax_list() -> Vec<Box<dyn SyntaxHighlight>> {
+        let mut list = syntax::highlighters()
+            .map(|it| Box::new(it) as Box<dyn SyntaxHighlight>)
+            .collect::<Vec<_>>();
+        list.push(Box::new(RustHighlight));
+        list
+    }
+
     fn select_syntax(extension: &str) -> Option<Box<dyn SyntaxHighlight>> {
-        let list: Vec<Box<dyn SyntaxHighlight>> = vec![Box::new(RustHighlight::new())];
-        list.into_iter()
+        Self::syntax_list()
+            .into_iter()
             .find(|it| it.extensions().contains(&extension))
     }
 
-    fn new() -> Self {
+    /// Picks a highlighter by the file's name, then by a glob pattern and then by its
+    /// extension
+    fn syntax_for_path(path: &Path) -> Option<Box<dyn SyntaxHighlight>> {
+        let name = path.file_name()?.to_str()?;
+        let full = path.to_str()?;
+        let list = Self::syntax_list();
+        let index = list
+            .iter()
+            .position(|it| it.filenames().contains(&name))
+            .or_else(|| {
+                list.iter().position(|it| {
+                    it.globs().iter().any(|glob| {
+                        filetype::glob_match(glob, if glob.contains('/') { full } else { name })
+                    })
+                })
+            });
+        match index {
+            Some(index) => list.into_iter().nth(index),
+            None => path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(Self::select_syntax),
+        }
+    }
+
+    /// Picks a highlighter by the interpreter a `#!` first line names. Versions such as the
+    /// 3 of `python3` may be left out of the definition
+    fn syntax_for_shebang(line: &str) -> Option<Box<dyn SyntaxHighlight>> {
+        let interpreter = filetype::interpreter(line)?;
+        let unversioned = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        Self::syntax_list().into_iter().find(|it| {
+            it.shebangs()
+                .iter()
+                .any(|name| *name == interpreter || *name == unversioned)
+        })
+    }
+
+    /// Finds a highlighter by its file type, one of its aliases or one of its extensions,
+    /// ignoring case
+    fn syntax_by_name(name: &str) -> Option<Box<dyn SyntaxHighlight>> {
+        Self::syntax_list().into_iter().find(|it| {
+            it.file_type().eq_ignore_ascii_case(name)
+                || it
+                    .aliases()
+                    .iter()
+                    .chain(it.extensions())
+                    .any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Works out the highlighter for a file: a modeline naming a file type wins over the
+    /// file's name, which wins over the interpreter on its `#!` line
+    fn detect_syntax(path: Option<&Path>, text: &str) -> Option<Box<dyn SyntaxHighlight>> {
+        filetype::modeline(text)
+            .and_then(Self::syntax_by_name)
+            .or_else(|| path.and_then(Self::syntax_for_path))
+            .or_else(|| text.lines().next().and_then(Self::syntax_for_shebang))
+    }
+
+    fn new(args: &Args, stdin: Option<Vec<u8>>) -> Self {
         let win_size = terminal::size()
-            .map(|(x, y)| (x as usize, y as usize - 2))
+            .map(|(x, y)| Self::editor_size((x as usize, y as usize)))
             .unwrap();
-        let mut syntax_highlight = None; // modify
-        Self {
+        let (config, mut notices) = Config::load();
+        notices.extend_from_slice(syntax::notices());
+        let mut buffers = Vec::new();
+        let mut stdin = stdin;
+        for file in &args.files {
+            let syntax_highlight = args.syntax.as_deref().and_then(Self::syntax_by_name);
+            let opened = match &file.source {
+                Source::Path(path) => Buffer::open(path.clone(), syntax_highlight, win_size)
+                    .map_err(|err| format!("Can't open {}: {}", path.display(), err)),
+                Source::Stdin => Ok(Buffer::from_bytes(
+                    &files::strip_escapes(&stdin.take().unwrap_or_default()),
+                    "standard input",
+                    syntax_highlight,
+                    win_size,
+                )),
+            };
+            match opened {
+                Ok((mut buffer, notice)) => {
+                    buffer
+                        .editor_rows
+                        .configure(&config, buffer.syntax_highlight.as_deref());
+                    let number_of_rows = buffer.editor_rows.number_of_rows();
+                    let at = file
+                        .location
+                        .as_ref()
+                        .and_then(|spec| parse_location(spec, 0, number_of_rows));
+                    if let Some(at) = at {
+                        buffer.cursor_controller.go_to(at, &buffer.editor_rows)
+                    }
+                    buffer.editor_rows.read_only |= args.read_only || args.pager;
+                    buffers.push(buffer);
+                    notices.extend(notice)
+                }
+                Err(notice) => notices.push(notice),
+            }
+        }
+        if buffers.is_empty() {
+            let mut buffer = Buffer::new(win_size);
+            buffer.editor_rows.configure(&config, None);
+            buffers.push(buffer)
+        }
+        let pager_help = "PAGER: Space/b = Page down/up | j/k = Line down/up | g/G = Top/Bottom | / = Find | q = Quit";
+        let help = "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-F = Find | Ctrl-R = Replace | Ctrl-Z = Undo | Ctrl-Y = Redo | Ctrl-X/C/V = Cut/Copy/Paste";
+        let mut output = Self {
             win_size,
             editor_contents: EditorContents::new(),
             cursor_controller: CursorController::new(win_size),
-            editor_rows: EditorRows::new(&mut syntax_highlight), //modify
+            editor_rows: EditorRows::new(),
             status_message: StatusMessage::new(
-                "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-F = Find".into(),
+                if notices.is_empty() {
+                    if args.pager { pager_help } else { help }.into()
+                } else {
+                    notices.join(" | ")
+                },
+                config.status_timeout,
             ),
             dirty: 0,
             search_index: SearchIndex::new(),
-            syntax_highlight,
-        }
+            syntax_highlight: None,
+            history: History::new(),
+            clipboard: None,
+            kill_ring: KillRing::new(),
+            search_history: Vec::new(),
+            file_history: Vec::new(),
+            command_history: Vec::new(),
+            location_history: Vec::new(),
+            buffers,
+            current_buffer: 0,
+            windows: vec![Window::new(0, win_size)],
+            current_window: 0,
+            layout: Layout::Window(0),
+            line_numbers: LineNumbers::Off,
+            config,
+            mode_indicator: None,
+            prompt_cursor: None,
+        };
+        /* the first slot stands in for the buffer held directly by `Output` */
+        output.swap_buffer(0);
+        output
+    }
+
+    /// Size of the text area, leaving room for the status and message bars
+    fn editor_size((columns, rows): (usize, usize)) -> (usize, usize) {
+        (columns, rows.saturating_sub(2))
+    }
+
+    fn resize(&mut self, terminal_size: (usize, usize)) {
+        self.win_size = Self::editor_size(terminal_size);
+        self.update_window_sizes();
+        self.cursor_controller.scroll(&self.editor_rows);
     }
 
     fn clear_screen() -> crossterm::Result<()> {
@@ -722,9 +1119,7 @@ impl Output {
     }
 
     fn find_callback(output: &mut Output, keyword: &str, key_code: KeyCode) {
-        if let Some((index, highlight)) = output.search_index.previous_highlight.take() {
-            output.editor_rows.get_editor_row_mut(index).highlight = highlight;
-        }
+        output.clear_match_highlight();
         match key_code {
             KeyCode::Esc | KeyCode::Enter => {
                 output.search_index.reset();
@@ -772,16 +1167,17 @@ impl Output {
                     }
                     let row = output.editor_rows.get_editor_row_mut(row_index);
                     let index = match output.search_index.x_direction.as_ref() {
-                        None => row.render.find(&keyword),
+                        None => row.render.find(keyword),
                         Some(dir) => {
                             let index = if matches!(dir, SearchDirection::Forward) {
-                                let start =
-                                    cmp::min(row.render.len(), output.search_index.x_index + 1);
-                                row.render[start..]
-                                    .find(&keyword)
-                                    .map(|index| index + start)
+                                let x_index = output.search_index.x_index;
+                                let start = row.render[x_index..]
+                                    .chars()
+                                    .next()
+                                    .map_or(x_index, |ch| x_index + ch.len_utf8());
+                                row.render[start..].find(keyword).map(|index| index + start)
                             } else {
-                                row.render[..output.search_index.x_index].rfind(&keyword)
+                                row.render[..output.search_index.x_index].rfind(keyword)
                             };
                             if index.is_none() {
                                 break;
@@ -792,12 +1188,16 @@ impl Output {
                     if let Some(index) = index {
                         output.search_index.previous_highlight =
                             Some((row_index, row.highlight.clone()));
-                        (index..index + keyword.len())
-                            .for_each(|index| row.highlight[index] = HighlightType::SearchMatch);
+                        row.highlight
+                            .iter_mut()
+                            .skip(index)
+                            .take(keyword.len())
+                            .for_each(|highlight| *highlight = HighlightType::SearchMatch);
                         output.cursor_controller.cursor_y = row_index;
                         output.search_index.y_index = row_index;
                         output.search_index.x_index = index;
-                        output.cursor_controller.cursor_x = row.get_row_content_x(index);
+                        output.cursor_controller.cursor_x =
+                            row.get_row_content_x(row.render[..index].width());
                         output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
                         break;
                     }
@@ -807,11 +1207,13 @@ impl Output {
     }
 
     fn find(&mut self) -> io::Result<()> {
+        self.cursor_controller.anchor = None;
         let cursor_controller = self.cursor_controller;
         if prompt!(
             self,
             "Search: {} (Use ESC / Arrows / Enter)",
-            callback = Output::find_callback
+            callback = Output::find_callback,
+            history = search_history
         )
         .is_none()
         {
@@ -820,6 +1222,139 @@ impl Output {
         Ok(())
     }
 
+    fn highlight_match(&mut self, at: Position, end_x: usize) {
+        let row = self.editor_rows.get_editor_row_mut(at.y);
+        self.search_index.previous_highlight = Some((at.y, row.highlight.clone()));
+        let (start, end) = (row.get_render_index(at.x), row.get_render_index(end_x));
+        row.highlight
+            .iter_mut()
+            .skip(start)
+            .take(end - start)
+            .for_each(|highlight| *highlight = HighlightType::SearchMatch);
+    }
+
+    fn clear_match_highlight(&mut self) {
+        if let Some((index, highlight)) = self.search_index.previous_highlight.take() {
+            self.editor_rows.get_editor_row_mut(index).highlight = highlight;
+        }
+    }
+
+    fn replace(&mut self) -> io::Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
+        self.cursor_controller.anchor = None;
+        let pattern = match prompt!(
+            self,
+            "Replace (regex): {} (ESC to cancel)",
+            history = search_history
+        ) {
+            None => return Ok(()),
+            Some(pattern) => pattern,
+        };
+        let regex = match Regex::new(&pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                let err = err.to_string();
+                self.status_message.set_message(format!(
+                    "Invalid pattern: {}",
+                    err.lines().last().unwrap_or_default()
+                ));
+                return Ok(());
+            }
+        };
+        let replacement = match prompt!(self, "Replace with: {} (ESC to cancel)", allow_empty) {
+            None => return Ok(()),
+            Some(replacement) => replacement,
+        };
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let start = if self.cursor_controller.cursor_y < number_of_rows {
+            self.cursor_controller.position()
+        } else {
+            Position { x: 0, y: 0 }
+        };
+        let start_index = if start.y < number_of_rows {
+            self.editor_rows.get_editor_row(start.y).byte_index(start.x)
+        } else {
+            0
+        };
+        /* search from the cursor to the end, then wrap around to where we started */
+        let passes = (start.y..number_of_rows)
+            .map(|y| (y, if y == start.y { start_index } else { 0 }, None))
+            .chain((0..start.y).map(|y| (y, 0, None)))
+            .chain((start_index > 0).then_some((start.y, 0, Some(start_index))));
+        let mut replaced = 0;
+        let mut replace_all = false;
+        self.history.begin_group(start);
+        'rows: for (y, from, limit) in passes {
+            let mut x = from;
+            loop {
+                let (match_start, match_end, text) = {
+                    let row = self.editor_rows.get_row(y);
+                    let captures = match regex.captures_at(row, x) {
+                        None => break,
+                        Some(captures) => captures,
+                    };
+                    let matched = captures.get(0).unwrap();
+                    if matches!(limit, Some(limit) if matched.start() >= limit) {
+                        break;
+                    }
+                    let mut text = String::new();
+                    captures.expand(&replacement, &mut text);
+                    (matched.start(), matched.end(), text)
+                };
+                let row = self.editor_rows.get_editor_row(y);
+                let start_x = row.grapheme_index(match_start);
+                let end_x = row.grapheme_index(match_end);
+                if !replace_all {
+                    self.cursor_controller
+                        .set_position(Position { x: start_x, y });
+                    self.highlight_match(Position { x: start_x, y }, end_x);
+                    self.status_message
+                        .set_message("Replace? (y)es / (n)o / (a)ll / (q)uit".into());
+                    self.refresh_screen()?;
+                    let key_event = Reader.read_key(self)?;
+                    self.clear_match_highlight();
+                    match key_event.code {
+                        KeyCode::Char('y') => {}
+                        KeyCode::Char('a') => replace_all = true,
+                        KeyCode::Char('n') => {
+                            x = match_end;
+                            if match_start == match_end {
+                                match self.editor_rows.get_row(y)[x..].chars().next() {
+                                    None => break,
+                                    Some(ch) => x += ch.len_utf8(),
+                                }
+                            }
+                            continue;
+                        }
+                        KeyCode::Char('q') | KeyCode::Esc => break 'rows,
+                        _ => continue,
+                    }
+                }
+                /* an empty match has nothing to select, and an anchor at the cursor would
+                end up selecting the replacement */
+                self.cursor_controller.anchor =
+                    (start_x != end_x).then_some(Position { x: start_x, y });
+                self.cursor_controller
+                    .set_position(Position { x: end_x, y });
+                self.insert_text(&text);
+                replaced += 1;
+                x = match_start + text.len();
+                if match_start == match_end {
+                    match self.editor_rows.get_row(y)[x..].chars().next() {
+                        None => break,
+                        Some(ch) => x += ch.len_utf8(),
+                    }
+                }
+            }
+        }
+        self.history.end_group();
+        self.status_message
+            .set_message(format!("Replaced {} occurrence(s)", replaced));
+        Ok(())
+    }
+
     fn draw_message_bar(&mut self) {
         queue!(
             self.editor_contents,
@@ -828,31 +1363,50 @@ impl Output {
         .unwrap();
         if let Some(msg) = self.status_message.message() {
             self.editor_contents
-                .push_str(&msg[..cmp::min(self.win_size.0, msg.len())]);
+                .push_str(truncate_to_width(msg, self.win_size.0));
         }
     }
 
     fn delete_char(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        if self.delete_selection().is_some() {
+            return;
+        }
+        self.cursor_controller.anchor = None;
         if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
             return;
         }
         if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
             return;
         }
-        if self.cursor_controller.cursor_x > 0 {
-            self.editor_rows
+        let cursor_before = self.cursor_controller.position();
+        let removed = if self.cursor_controller.cursor_x > 0 {
+            let removed = self
+                .editor_rows
                 .get_editor_row_mut(self.cursor_controller.cursor_y)
                 .delete_char(self.cursor_controller.cursor_x - 1);
             self.cursor_controller.cursor_x -= 1;
+            removed
         } else {
-            let previous_row_content = self
+            self.cursor_controller.cursor_x = self
                 .editor_rows
-                .get_row(self.cursor_controller.cursor_y - 1);
-            self.cursor_controller.cursor_x = previous_row_content.len();
+                .get_editor_row(self.cursor_controller.cursor_y - 1)
+                .len();
             self.editor_rows
                 .join_adjacent_rows(self.cursor_controller.cursor_y);
             self.cursor_controller.cursor_y -= 1;
-        }
+            "\n".into()
+        };
+        self.history.record(
+            Edit::Delete {
+                at: self.cursor_controller.position(),
+                text: removed,
+            },
+            cursor_before,
+            self.cursor_controller.position(),
+        );
         if let Some(it) = self.syntax_highlight.as_ref() {
             it.update_syntax(
                 self.cursor_controller.cursor_y,
@@ -863,6 +1417,41 @@ impl Output {
     }
 
     fn insert_newline(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        if self.selection().is_some() {
+            return self.insert_text("\n");
+        }
+        self.cursor_controller.anchor = None;
+        let cursor_before = self.cursor_controller.position();
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if cursor_before.y == number_of_rows && number_of_rows > 0 {
+            let at = Position {
+                x: self.editor_rows.get_editor_row(number_of_rows - 1).len(),
+                y: number_of_rows - 1,
+            };
+            self.history.record(
+                Edit::Insert {
+                    at,
+                    text: "\n".into(),
+                },
+                cursor_before,
+                cursor_before,
+            )
+        } else if cursor_before.y < number_of_rows {
+            self.history.record(
+                Edit::Insert {
+                    at: cursor_before,
+                    text: "\n".into(),
+                },
+                cursor_before,
+                Position {
+                    x: 0,
+                    y: cursor_before.y + 1,
+                },
+            )
+        }
         if self.cursor_controller.cursor_x == 0 {
             self.editor_rows
                 .insert_row(self.cursor_controller.cursor_y, String::new())
@@ -870,10 +1459,8 @@ impl Output {
             let current_row = self
                 .editor_rows
                 .get_editor_row_mut(self.cursor_controller.cursor_y);
-            let new_row_content = current_row.row_content[self.cursor_controller.cursor_x..].into();
-            current_row
-                .row_content
-                .truncate(self.cursor_controller.cursor_x);
+            let index = current_row.byte_index(self.cursor_controller.cursor_x);
+            let new_row_content = current_row.row_content.split_off(index);
             EditorRows::render_row(current_row);
             self.editor_rows
                 .insert_row(self.cursor_controller.cursor_y + 1, new_row_content);
@@ -893,13 +1480,65 @@ impl Output {
         self.dirty += 1;
     }
 
+    /// Inserts a tab, or spaces up to the next indent stop when the file type indents with
+    /// spaces
+    fn insert_tab(&mut self) {
+        let indentation = self.editor_rows.indentation;
+        if indentation.style == IndentStyle::Tabs {
+            return self.insert_char('\t');
+        }
+        if self.is_read_only() {
+            return;
+        }
+        let render_x = if self.cursor_controller.cursor_y < self.editor_rows.number_of_rows() {
+            self.cursor_controller.get_render_x(
+                self.editor_rows
+                    .get_editor_row(self.cursor_controller.cursor_y),
+            )
+        } else {
+            0
+        };
+        let width = indentation.indent_width - render_x % indentation.indent_width;
+        self.insert_text(&" ".repeat(width))
+    }
+
     fn insert_char(&mut self, ch: char) {
+        if self.is_read_only() {
+            return;
+        }
+        if self.selection().is_some() {
+            return self.insert_text(&ch.to_string());
+        }
+        self.cursor_controller.anchor = None;
+        /* the row typing past the end creates is undone along with the character */
+        let mut new_row = false;
         if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+            if self.cursor_controller.cursor_y > 0 {
+                new_row = true;
+                let at = Position {
+                    x: self
+                        .editor_rows
+                        .get_editor_row(self.cursor_controller.cursor_y - 1)
+                        .len(),
+                    y: self.cursor_controller.cursor_y - 1,
+                };
+                let cursor = self.cursor_controller.position();
+                self.history.record(
+                    Edit::Insert {
+                        at,
+                        text: "\n".into(),
+                    },
+                    cursor,
+                    cursor,
+                );
+            }
             self.editor_rows
                 .insert_row(self.editor_rows.number_of_rows(), String::new());
             self.dirty += 1;
         }
-        self.editor_rows
+        let cursor_before = self.cursor_controller.position();
+        self.cursor_controller.cursor_x = self
+            .editor_rows
             .get_editor_row_mut(self.cursor_controller.cursor_y)
             .insert_char(self.cursor_controller.cursor_x, ch);
         if let Some(it) = self.syntax_highlight.as_ref() {
@@ -908,95 +1547,508 @@ impl Output {
                 &mut self.editor_rows.row_contents,
             )
         }
-        self.cursor_controller.cursor_x += 1;
+        let edit = Edit::Insert {
+            at: cursor_before,
+            text: ch.to_string(),
+        };
+        if new_row {
+            self.history
+                .record_joined(edit, self.cursor_controller.position())
+        } else {
+            self.history
+                .record(edit, cursor_before, self.cursor_controller.position())
+        }
         self.dirty += 1;
     }
 
-    fn draw_status_bar(&mut self) {
-        self.editor_contents
-            .push_str(&style::Attribute::Reverse.to_string());
-        let info = format!(
-            "{} {} -- {} lines",
+    fn update_syntax_rows(&mut self, from: usize, to: usize) {
+        if let Some(it) = self.syntax_highlight.as_ref() {
+            for i in from..cmp::min(to + 1, self.editor_rows.number_of_rows()) {
+                it.update_syntax(i, &mut self.editor_rows.row_contents)
+            }
+        }
+    }
+
+    fn apply_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Insert { at, text } => {
+                let end = self.editor_rows.insert_text(*at, text);
+                self.update_syntax_rows(at.y, end.y)
+            }
+            Edit::Delete { at, text } => {
+                self.editor_rows
+                    .delete_text(*at, history::text_end(*at, text));
+                self.update_syntax_rows(at.y, at.y)
+            }
+        }
+    }
+
+    fn apply_history(&mut self, edits: Vec<Edit>, cursor: Position) {
+        edits.iter().for_each(|edit| self.apply_edit(edit));
+        self.cursor_controller.set_position(cursor);
+        self.cursor_controller.anchor = None;
+        if self.history.is_at_save_point() {
+            self.dirty = 0
+        } else {
+            self.dirty += 1
+        }
+    }
+
+    /// Re-highlights every row with `syntax_highlight`, or clears the colours for `None`
+    fn set_syntax(&mut self, syntax_highlight: Option<Box<dyn SyntaxHighlight>>) {
+        self.syntax_highlight = syntax_highlight;
+        self.editor_rows
+            .configure(&self.config, self.syntax_highlight.as_deref());
+        if self.syntax_highlight.is_none() {
             self.editor_rows
-                .filename
-                .as_ref()
-                .and_then(|path| path.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("[No Name]"),
-            if self.dirty > 0 { "(modified)" } else { "" },
-            self.editor_rows.number_of_rows()
+                .row_contents
+                .iter_mut()
+                .for_each(|row| row.highlight.clear());
+        }
+        self.update_syntax_rows(0, self.editor_rows.number_of_rows());
+    }
+
+    fn choose_syntax(&mut self) -> io::Result<()> {
+        if let Some(name) = prompt!(
+            self,
+            "Syntax: {} (name, \"none\", \"auto\" or ESC to cancel)",
+            complete = complete_syntax
+        ) {
+            self.set_syntax_by_name(&name)
+        }
+        Ok(())
+    }
+
+    /// Switches highlighting to the file type `name`, off for `none`, or back to what the
+    /// file itself suggests for `auto`
+    fn set_syntax_by_name(&mut self, name: &str) {
+        if name.eq_ignore_ascii_case("none") {
+            self.set_syntax(None);
+            self.status_message
+                .set_message("Syntax highlighting off".into());
+        } else if name.eq_ignore_ascii_case("auto") {
+            let syntax_highlight = self.detect_syntax_again();
+            let message = match &syntax_highlight {
+                Some(it) => format!("Syntax: {}", it.file_type()),
+                None => "No syntax detected".to_string(),
+            };
+            self.set_syntax(syntax_highlight);
+            self.status_message.set_message(message);
+        } else if let Some(syntax_highlight) = Self::syntax_by_name(name) {
+            let message = format!("Syntax: {}", syntax_highlight.file_type());
+            self.set_syntax(Some(syntax_highlight));
+            self.status_message.set_message(message);
+        } else {
+            let available = Self::syntax_list()
+                .iter()
+                .map(|it| it.file_type().to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            self.status_message
+                .set_message(format!("Unknown syntax {}; available: {}", name, available));
+        }
+    }
+
+    /// Runs file type detection on the buffer as it is now. Only the rows a modeline or `#!`
+    /// line can be on are looked at
+    fn detect_syntax_again(&self) -> Option<Box<dyn SyntaxHighlight>> {
+        let rows = &self.editor_rows.row_contents;
+        let head = cmp::min(rows.len(), filetype::MODELINES);
+        let tail = cmp::max(head, rows.len().saturating_sub(filetype::MODELINES));
+        let text = rows[..head]
+            .iter()
+            .chain(&rows[tail..])
+            .map(|row| row.row_content.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        Self::detect_syntax(self.editor_rows.filename.as_deref(), &text)
+    }
+
+    /// Names the buffer, switching highlighting to match the new name
+    fn set_filename(&mut self, path: PathBuf) {
+        let syntax_highlight = Output::syntax_for_path(&path);
+        if syntax_highlight.is_some() {
+            self.set_syntax(syntax_highlight)
+        }
+        self.editor_rows.filename = Some(path)
+    }
+
+    fn go_to_line(&mut self) -> io::Result<()> {
+        let spec = match prompt!(
+            self,
+            "Go to: {} (line[:col], +N, -N or N%, ESC to cancel)",
+            history = location_history
+        ) {
+            None => return Ok(()),
+            Some(spec) => spec,
+        };
+        match parse_location(
+            &spec,
+            self.cursor_controller.cursor_y,
+            self.editor_rows.number_of_rows(),
+        ) {
+            Some(at) => {
+                self.history.seal();
+                self.cursor_controller.go_to(at, &self.editor_rows)
+            }
+            None => self
+                .status_message
+                .set_message(format!("Invalid location: {}", spec)),
+        }
+        Ok(())
+    }
+
+    /// Tells the user when the current buffer can't be edited
+    fn is_read_only(&mut self) -> bool {
+        if self.editor_rows.read_only {
+            self.status_message
+                .set_message("Buffer is read-only (Alt-R to allow editing)".into());
+        }
+        self.editor_rows.read_only
+    }
+
+    fn toggle_read_only(&mut self) {
+        self.editor_rows.read_only = !self.editor_rows.read_only;
+        let message = match (self.editor_rows.read_only, self.editor_rows.lossy) {
+            (true, _) => "Buffer is now read-only",
+            (false, false) => "Buffer is now writable",
+            (false, true) => "Buffer is now writable; saving replaces invalid bytes with U+FFFD",
+        };
+        self.status_message.set_message(message.into());
+    }
+
+    fn undo(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        match self.history.undo() {
+            Some((edits, cursor)) => self.apply_history(edits, cursor),
+            None => self.status_message.set_message("Nothing to undo".into()),
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        match self.history.redo() {
+            Some((edits, cursor)) => self.apply_history(edits, cursor),
+            None => self.status_message.set_message("Nothing to redo".into()),
+        }
+    }
+
+    fn delete_selection(&mut self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        let cursor_before = self.cursor_controller.position();
+        let removed = self.editor_rows.delete_text(start, end);
+        self.update_syntax_rows(start.y, start.y);
+        self.cursor_controller.set_position(start);
+        self.cursor_controller.anchor = None;
+        self.history.record(
+            Edit::Delete {
+                at: start,
+                text: removed.clone(),
+            },
+            cursor_before,
+            start,
+        );
+        self.dirty += 1;
+        Some(removed)
+    }
+
+    /// Inserts `text` at the cursor, replacing the selection if there is one
+    fn insert_text(&mut self, text: &str) {
+        let cursor_before = self.cursor_controller.position();
+        self.history.begin_group(cursor_before);
+        self.delete_selection();
+        let at = self.cursor_controller.position();
+        let end = self.editor_rows.insert_text(at, text);
+        self.update_syntax_rows(at.y, end.y);
+        self.cursor_controller.set_position(end);
+        self.history.record(
+            Edit::Insert {
+                at,
+                text: text.into(),
+            },
+            cursor_before,
+            end,
+        );
+        self.history.end_group();
+        self.dirty += 1;
+    }
+
+    fn copy(&mut self) {
+        match self.selection() {
+            None => self.status_message.set_message("Nothing selected".into()),
+            Some((start, end)) => {
+                let text = self.editor_rows.get_text(start, end);
+                self.status_message
+                    .set_message(format!("Copied {} bytes", text.len()));
+                self.clipboard = Some(text);
+            }
+        }
+    }
+
+    fn cut(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        self.history.seal();
+        match self.delete_selection() {
+            None => self.status_message.set_message("Nothing selected".into()),
+            Some(text) => {
+                self.history.seal();
+                self.clipboard = Some(text)
+            }
+        }
+    }
+
+    fn paste(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        match self.clipboard.take() {
+            None => self.status_message.set_message("Clipboard is empty".into()),
+            Some(text) => {
+                self.insert_text(&text);
+                self.clipboard = Some(text)
+            }
+        }
+    }
+
+    fn draw_status_bar(
+        &self,
+        contents: &mut EditorContents,
+        view: &View,
+        rect: Rect,
+        active: bool,
+    ) {
+        let width = rect.width;
+        contents.push_str(&style::Attribute::Reverse.to_string());
+        if !active {
+            contents.push_str(&style::Attribute::Dim.to_string());
+        }
+        let buffer_info = if self.buffers.len() > 1 {
+            format!("[{}/{}] ", view.buffer + 1, self.buffers.len())
+        } else {
+            String::new()
+        };
+        let mode = match &self.mode_indicator {
+            Some(mode) if active => format!("{} | ", mode),
+            _ => String::new(),
+        };
+        let info = format!(
+            "{}{}{}{} {} -- {} lines",
+            mode,
+            buffer_info,
+            view.editor_rows.display_name(),
+            if view.editor_rows.read_only {
+                " [RO]"
+            } else {
+                ""
+            },
+            if view.dirty > 0 { "(modified)" } else { "" },
+            view.editor_rows.number_of_rows()
         );
-        let info_len = cmp::min(info.len(), self.win_size.0);
+        let info = truncate_to_width(&info, width);
+        let info_len = info.width();
         /* modify the following */
+        let format = view.editor_rows.format;
         let line_info = format!(
-            "{} | {}/{}",
-            self.syntax_highlight
-                .as_ref()
+            "{} | {}{} | {}/{}",
+            view.syntax_highlight
                 .map(|highlight| highlight.file_type())
                 .unwrap_or("no ft"),
-            self.cursor_controller.cursor_y + 1,
-            self.editor_rows.number_of_rows()
+            if format.mixed {
+                "mixed"
+            } else {
+                format.line_ending.name()
+            },
+            if format.bom { " BOM" } else { "" },
+            view.cursor_controller.cursor_y + 1,
+            view.editor_rows.number_of_rows()
         );
-        self.editor_contents.push_str(&info[..info_len]);
-        for i in info_len..self.win_size.0 {
-            if self.win_size.0 - i == line_info.len() {
-                self.editor_contents.push_str(&line_info);
+        contents.push_str(info);
+        for i in info_len..width {
+            if width - i == line_info.width() {
+                contents.push_str(&line_info);
                 break;
             } else {
-                self.editor_contents.push(' ')
+                contents.push(' ')
+            }
+        }
+        contents.push_str(&style::Attribute::Reset.to_string());
+    }
+
+    fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.is_read_only() {
+            return;
+        }
+        if self.editor_rows.format.mixed {
+            self.strip_carriage_returns()
+        }
+        if self.editor_rows.format.line_ending != line_ending {
+            self.editor_rows.format.line_ending = line_ending;
+            self.dirty += 1;
+            self.history.forget_save_point();
+        }
+        self.status_message
+            .set_message(format!("Line endings: {}", line_ending.name()));
+    }
+
+    /// Takes the `'\r'` off the CRLF lines of a file with mixed line endings, so that one
+    /// ending is written for every line. Undo puts them back
+    fn strip_carriage_returns(&mut self) {
+        let cursor = self.cursor_controller.position();
+        self.history.begin_group(cursor);
+        for y in 0..self.editor_rows.number_of_rows() {
+            let row = self.editor_rows.get_editor_row(y);
+            if row.row_content.ends_with('\r') {
+                let at = Position {
+                    x: row.len() - 1,
+                    y,
+                };
+                let end = Position { x: at.x + 1, y };
+                let text = self.editor_rows.delete_text(at, end);
+                self.history
+                    .record(Edit::Delete { at, text }, cursor, cursor);
+                self.update_syntax_rows(y, y)
             }
         }
-        self.editor_contents
-            .push_str(&style::Attribute::Reset.to_string());
-        self.editor_contents.push_str("\r\n");
+        self.history.end_group();
+        if cursor.y < self.editor_rows.number_of_rows() {
+            let row_len = self.editor_rows.get_editor_row(cursor.y).len();
+            self.cursor_controller.cursor_x = cmp::min(cursor.x, row_len)
+        }
+        self.editor_rows.format.mixed = false;
+        self.dirty += 1;
+        self.history.forget_save_point()
+    }
+
+    fn selection(&self) -> Option<(Position, Position)> {
+        self.cursor_controller.selection(&self.editor_rows)
     }
 
-    fn draw_rows(&mut self) {
-        let screen_rows = self.win_size.1;
-        let screen_columns = self.win_size.0;
+    fn draw_rows(contents: &mut EditorContents, view: &View, rect: Rect) {
+        let editor_rows = view.editor_rows;
+        let cursor_controller = &view.cursor_controller;
+        let screen_rows = cursor_controller.screen_rows;
+        let screen_columns = cursor_controller.screen_columns;
+        let gutter_width = rect.width.saturating_sub(screen_columns);
         for i in 0..screen_rows {
-            let file_row = i + self.cursor_controller.row_offset;
-            if file_row >= self.editor_rows.number_of_rows() {
-                if self.editor_rows.number_of_rows() == 0 && i == screen_rows / 3 {
+            queue!(contents, cursor::MoveTo(rect.x as u16, (rect.y + i) as u16)).unwrap();
+            let file_row = i + cursor_controller.row_offset;
+            if gutter_width > 0 {
+                if file_row < editor_rows.number_of_rows() {
+                    let label = view
+                        .line_numbers
+                        .label(file_row, cursor_controller.cursor_y);
+                    contents.push_str(&format!("{:>1$} ", label, gutter_width - 1));
+                } else {
+                    (0..gutter_width).for_each(|_| contents.push(' '));
+                }
+            }
+            let drawn = if screen_columns == 0 {
+                0
+            } else if file_row >= editor_rows.number_of_rows() {
+                if editor_rows.number_of_rows() == 0 && i == screen_rows / 3 {
                     let mut welcome = format!("Pound Editor --- Version {}", VERSION);
                     if welcome.len() > screen_columns {
                         welcome.truncate(screen_columns)
                     }
                     let mut padding = (screen_columns - welcome.len()) / 2;
+                    let drawn = padding + welcome.len();
                     if padding != 0 {
-                        self.editor_contents.push('~');
+                        contents.push('~');
                         padding -= 1
                     }
-                    (0..padding).for_each(|_| self.editor_contents.push(' '));
-                    self.editor_contents.push_str(&welcome);
+                    (0..padding).for_each(|_| contents.push(' '));
+                    contents.push_str(&welcome);
+                    drawn
                 } else {
-                    self.editor_contents.push('~');
+                    contents.push('~');
+                    1
                 }
             } else {
-                let row = self.editor_rows.get_editor_row(file_row);
+                let selected = cursor_controller.selected_range(editor_rows, file_row);
+                let row = editor_rows.get_editor_row(file_row);
                 let render = &row.render;
-                let column_offset = self.cursor_controller.column_offset;
-                let len = cmp::min(render.len().saturating_sub(column_offset), screen_columns);
-                let start = if len == 0 { 0 } else { column_offset };
-                let render = render.chars().skip(start).take(len).collect::<String>();
-                self.syntax_highlight
-                    .as_ref()
-                    .map(|syntax_highlight| {
-                        syntax_highlight.color_row(
-                            &render,
-                            &row.highlight[start..start + len],
-                            &mut self.editor_contents,
-                        )
-                    })
-                    .unwrap_or_else(|| self.editor_contents.push_str(&render));
-            }
-            queue!(
-                self.editor_contents,
-                terminal::Clear(ClearType::UntilNewLine)
-            )
-            .unwrap();
-            self.editor_contents.push_str("\r\n");
+                let column_offset = cursor_controller.column_offset;
+                let limit = column_offset + screen_columns;
+                /* wide graphemes cut off by either edge of the screen are drawn as spaces */
+                let (mut start, mut end) = (render.len(), render.len());
+                let (mut left_padding, mut right_padding) = (0, 0);
+                let mut render_x = 0;
+                for (index, grapheme) in render.grapheme_indices(true) {
+                    if render_x >= column_offset && start == render.len() {
+                        start = index;
+                        left_padding = cmp::min(render_x - column_offset, screen_columns);
+                    }
+                    let width = grapheme.width();
+                    if render_x + width > limit {
+                        end = index;
+                        right_padding = limit.saturating_sub(cmp::max(render_x, column_offset));
+                        break;
+                    }
+                    render_x += width;
+                }
+                start = cmp::min(start, end);
+                let (selection_start, selection_end) = selected
+                    .map(|(from, to)| (from.clamp(start, end), to.clamp(start, end)))
+                    .unwrap_or((end, end));
+                (0..left_padding).for_each(|_| contents.push(' '));
+                [
+                    (start..selection_start, false),
+                    (selection_start..selection_end, true),
+                    (selection_end..end, false),
+                ]
+                .into_iter()
+                .filter(|(range, _)| !range.is_empty())
+                .for_each(|(range, reverse)| {
+                    if reverse {
+                        contents.push_str(&style::Attribute::Reverse.to_string());
+                    }
+                    let render = &render[range.clone()];
+                    view.syntax_highlight
+                        .map(|syntax_highlight| {
+                            syntax_highlight.color_row(
+                                render,
+                                &row.highlight[range],
+                                view.theme,
+                                contents,
+                            )
+                        })
+                        .unwrap_or_else(|| contents.push_str(render));
+                    if reverse {
+                        contents.push_str(&style::Attribute::NoReverse.to_string());
+                    }
+                });
+                (0..right_padding).for_each(|_| contents.push(' '));
+                left_padding + render[start..end].width() + right_padding
+            };
+            (drawn..screen_columns).for_each(|_| contents.push(' '));
+        }
+    }
+
+    fn move_page(&mut self, direction: KeyCode, selecting: bool) {
+        self.history.seal();
+        self.cursor_controller.update_anchor(selecting);
+        if matches!(direction, KeyCode::PageUp) {
+            self.cursor_controller.cursor_y = self.cursor_controller.row_offset
+        } else {
+            self.cursor_controller.cursor_y = cmp::min(
+                (self.cursor_controller.screen_rows + self.cursor_controller.row_offset)
+                    .saturating_sub(1),
+                self.editor_rows.number_of_rows(),
+            );
         }
+        (0..self.cursor_controller.screen_rows).for_each(|_| {
+            self.move_cursor(if matches!(direction, KeyCode::PageUp) {
+                KeyCode::Up
+            } else {
+                KeyCode::Down
+            });
+        })
     }
 
     fn move_cursor(&mut self, direction: KeyCode) {
@@ -1005,16 +2057,67 @@ impl Output {
     }
 
     fn refresh_screen(&mut self) -> crossterm::Result<()> {
+        /* the gutter grows with the line count */
+        self.update_window_sizes();
         self.cursor_controller.scroll(&self.editor_rows);
-        queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
-        self.draw_rows();
-        self.draw_status_bar();
+        let mut contents = mem::replace(&mut self.editor_contents, EditorContents::new());
+        queue!(contents, cursor::Hide)?;
+        let panes = self.panes();
+        for pane in &panes {
+            let view = self.view(pane.window);
+            let rect = pane.rect;
+            Self::draw_rows(&mut contents, &view, rect);
+            let status_row = rect.y + view.cursor_controller.screen_rows;
+            if status_row < rect.y + rect.height {
+                queue!(contents, cursor::MoveTo(rect.x as u16, status_row as u16))?;
+                self.draw_status_bar(
+                    &mut contents,
+                    &view,
+                    rect,
+                    pane.window == self.current_window,
+                );
+            }
+            if pane.separator {
+                for y in rect.y..rect.y + rect.height {
+                    queue!(
+                        contents,
+                        cursor::MoveTo((rect.x + rect.width) as u16, y as u16)
+                    )?;
+                    contents.push('│');
+                }
+            }
+        }
+        queue!(contents, cursor::MoveTo(0, (self.win_size.1 + 1) as u16))?;
+        self.editor_contents = contents;
         self.draw_message_bar();
-        let cursor_x = self.cursor_controller.render_x - self.cursor_controller.column_offset;
-        let cursor_y = self.cursor_controller.cursor_y - self.cursor_controller.row_offset;
+        let rect = panes
+            .iter()
+            .find(|pane| pane.window == self.current_window)
+            .map(|pane| pane.rect)
+            .unwrap();
+        if let Some(column) = self.prompt_cursor {
+            let column = cmp::min(column, self.win_size.0.saturating_sub(1));
+            let message_row = self.win_size.1 + 1;
+            queue!(
+                self.editor_contents,
+                cursor::MoveTo(column as u16, message_row as u16),
+                cursor::Show
+            )?;
+            return self.editor_contents.flush();
+        }
+        let gutter_width = rect.width - self.cursor_controller.screen_columns;
+        let cursor_x = gutter_width
+            + self
+                .cursor_controller
+                .render_x
+                .saturating_sub(self.cursor_controller.column_offset);
+        let cursor_y = self
+            .cursor_controller
+            .cursor_y
+            .saturating_sub(self.cursor_controller.row_offset);
         queue!(
             self.editor_contents,
-            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+            cursor::MoveTo((rect.x + cursor_x) as u16, (rect.y + cursor_y) as u16),
             cursor::Show
         )?;
         self.editor_contents.flush()
@@ -1024,11 +2127,17 @@ impl Output {
 struct Reader;
 
 impl Reader {
-    fn read_key(&self) -> crossterm::Result<KeyEvent> {
+    /// Waits for a key press, redrawing `output` whenever the terminal is resized meanwhile
+    fn read_key(&self, output: &mut Output) -> crossterm::Result<KeyEvent> {
         loop {
             if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
+                match event::read()? {
+                    Event::Key(event) => return Ok(event),
+                    Event::Resize(columns, rows) => {
+                        output.resize((columns as usize, rows as usize));
+                        output.refresh_screen()?;
+                    }
+                    _ => {}
                 }
             }
         }
@@ -1039,146 +2148,356 @@ struct Editor {
     reader: Reader,
     output: Output,
     quit_times: u8,
+    pager: bool,
+    keymap: Keymap,
+    vim: Option<Vim>,
+    /// Set by `set-mark`: cursor movement then extends the selection instead of dropping it
+    mark: bool,
+    last_command: Option<Command>,
 }
 
 impl Editor {
-    fn new() -> Self {
+    fn new(args: &Args, stdin: Option<Vec<u8>>) -> Self {
+        let output = Output::new(args, stdin);
+        let mut keymap = Keymap::new(output.config.preset);
+        for (keys, command) in &output.config.keys {
+            keymap.bind(keys.clone(), *command)
+        }
         Self {
+            keymap,
             reader: Reader,
-            output: Output::new(),
-            quit_times: QUIT_TIMES,
+            quit_times: output.config.quit_times,
+            vim: output.config.vim.then(Vim::new),
+            mark: false,
+            last_command: None,
+            output,
+            pager: args.pager,
+        }
+    }
+
+    /// The names of the buffers with unsaved changes, comma separated
+    fn unsaved(&self) -> String {
+        self.output
+            .buffer_list()
+            .into_iter()
+            .filter(|(_, dirty)| *dirty)
+            .map(|(name, _)| name)
+            .collect::<Vec<&str>>()
+            .join(", ")
+    }
+
+    /// Whether the editor should exit, warning first while buffers are unsaved
+    fn quit(&mut self) -> bool {
+        let unsaved = self.unsaved();
+        if !unsaved.is_empty() && self.quit_times > 0 {
+            let key = self
+                .keymap
+                .chord_for(Command::Quit)
+                .unwrap_or_else(|| "the quit key".into());
+            self.output.status_message.set_message(format!(
+                "WARNING!!! Unsaved changes in {}. Press {} {} more times to quit.",
+                unsaved, key, self.quit_times
+            ));
+            self.quit_times -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// less-style keys for read-only buffers in pager mode. Returns `None` for keys it
+    /// leaves to the normal bindings
+    fn pager_keypress(&mut self, key: KeyEvent) -> crossterm::Result<Option<bool>> {
+        if key.modifiers != KeyModifiers::NONE && key.modifiers != KeyModifiers::SHIFT {
+            return Ok(None);
+        }
+        match key.code {
+            KeyCode::Char(' ' | 'f') => self.output.move_page(KeyCode::PageDown, false),
+            KeyCode::Char('b') => self.output.move_page(KeyCode::PageUp, false),
+            KeyCode::Char('j') | KeyCode::Enter => self.output.move_cursor(KeyCode::Down),
+            KeyCode::Char('k') => self.output.move_cursor(KeyCode::Up),
+            KeyCode::Char('g') => self
+                .output
+                .cursor_controller
+                .go_to(Position { y: 0, x: 0 }, &self.output.editor_rows),
+            KeyCode::Char('G') => {
+                let last = self.output.editor_rows.number_of_rows();
+                self.output
+                    .cursor_controller
+                    .go_to(Position { y: last, x: 0 }, &self.output.editor_rows)
+            }
+            KeyCode::Char('/') => self.output.find()?,
+            KeyCode::Char('q') => return Ok(Some(!self.quit())),
+            _ => return Ok(None),
         }
+        Ok(Some(true))
     }
 
     fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
-                if self.output.dirty > 0 && self.quit_times > 0 {
-                    self.output.status_message.set_message(format!(
-                        "WARNING!!! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
-                        self.quit_times
-                    ));
-                    self.quit_times -= 1;
-                    return Ok(true);
-                }
-                return Ok(false);
+        let key = self.reader.read_key(&mut self.output)?;
+        self.handle_key(key)
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> crossterm::Result<bool> {
+        let last_command = self.last_command.take();
+        if self.pager && self.output.editor_rows.read_only {
+            if let Some(keep_running) = self.pager_keypress(key)? {
+                return Ok(keep_running);
             }
-            KeyEvent {
-                code:
-                    direction
-                    @
-                    (KeyCode::Up
-                    | KeyCode::Down
-                    | KeyCode::Left
-                    | KeyCode::Right
-                    | KeyCode::Home
-                    | KeyCode::End),
-                modifiers: KeyModifiers::NONE,
-            } => self.output.move_cursor(direction),
-            KeyEvent {
-                code: val @ (KeyCode::PageUp | KeyCode::PageDown),
-                modifiers: KeyModifiers::NONE,
-            } => {
-                if matches!(val, KeyCode::PageUp) {
-                    self.output.cursor_controller.cursor_y =
-                        self.output.cursor_controller.row_offset
-                } else {
-                    self.output.cursor_controller.cursor_y = cmp::min(
-                        self.output.win_size.1 + self.output.cursor_controller.row_offset - 1,
-                        self.output.editor_rows.number_of_rows(),
-                    );
+        }
+        if let Some(keep_running) = self.vim_keypress(key)? {
+            self.quit_times = self.output.config.quit_times;
+            return Ok(keep_running);
+        }
+        let mut keys = vec![keymap::normalize(key)];
+        loop {
+            match self.keymap.lookup(&keys) {
+                Lookup::Command(command) => {
+                    self.last_command = last_command;
+                    return self.execute(command);
                 }
-                (0..self.output.win_size.1).for_each(|_| {
-                    self.output.move_cursor(if matches!(val, KeyCode::PageUp) {
-                        KeyCode::Up
-                    } else {
-                        KeyCode::Down
-                    });
-                })
+                Lookup::Prefix => {
+                    self.output
+                        .status_message
+                        .set_message(format!("{}-", describe_chord(&keys)));
+                    self.output.refresh_screen()?;
+                    keys.push(keymap::normalize(self.reader.read_key(&mut self.output)?));
+                }
+                Lookup::Unbound => break,
             }
-            KeyEvent {
-                code: KeyCode::Char('s'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
-                if matches!(self.output.editor_rows.filename, None) {
-                    let prompt = prompt!(&mut self.output, "Save as : {} (ESC to cancel)")
-                        .map(|it| it.into());
-                    if prompt.is_none() {
-                        self.output
-                            .status_message
-                            .set_message("Save Aborted".into());
-                        return Ok(true);
-                    }
-                    /* add the following */
-                    prompt
-                        .as_ref()
-                        .and_then(|path: &PathBuf| path.extension())
-                        .and_then(|ext| ext.to_str())
-                        .map(|ext| {
-                            Output::select_syntax(ext).map(|syntax| {
-                                let highlight = self.output.syntax_highlight.insert(syntax);
-                                for i in 0..self.output.editor_rows.number_of_rows() {
-                                    highlight
-                                        .update_syntax(i, &mut self.output.editor_rows.row_contents)
-                                }
-                            })
-                        });
+        }
+        match keys[..] {
+            [KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE,
+            }] => self.output.insert_char(ch),
+            [_] => {}
+            _ => self
+                .output
+                .status_message
+                .set_message(format!("{} is not bound", describe_chord(&keys))),
+        }
+        self.quit_times = self.output.config.quit_times;
+        Ok(true)
+    }
 
-                    self.output.editor_rows.filename = prompt
+    /// Waits for a key sequence and tells what it would do
+    fn describe_key(&mut self) -> crossterm::Result<()> {
+        self.output
+            .status_message
+            .set_message("Describe key: press a key".into());
+        let mut keys = Vec::new();
+        loop {
+            self.output.refresh_screen()?;
+            keys.push(keymap::normalize(self.reader.read_key(&mut self.output)?));
+            let description = describe_chord(&keys);
+            let message = match self.keymap.lookup(&keys) {
+                Lookup::Prefix => {
+                    self.output
+                        .status_message
+                        .set_message(format!("Describe key: {}-", description));
+                    continue;
                 }
-                self.output.editor_rows.save().map(|len| {
+                Lookup::Command(command) => format!("{} runs {}", description, command.name()),
+                Lookup::Unbound => match keys[..] {
+                    [KeyEvent {
+                        code: KeyCode::Char(_),
+                        modifiers: KeyModifiers::NONE,
+                    }] => format!("{} inserts itself", description),
+                    _ => format!("{} is not bound", description),
+                },
+            };
+            self.output.status_message.set_message(message);
+            return Ok(());
+        }
+    }
+
+    fn save(&mut self) -> crossterm::Result<()> {
+        if self.output.is_read_only() {
+            return Ok(());
+        }
+        if self.output.editor_rows.filename.is_none() {
+            match prompt!(
+                &mut self.output,
+                "Save as : {} (ESC to cancel)",
+                history = file_history,
+                complete = complete_path
+            ) {
+                Some(path) => self.output.set_filename(path.into()),
+                None => {
                     self.output
                         .status_message
-                        .set_message(format!("{} bytes written to disk", len));
-                    self.output.dirty = 0
-                })?;
+                        .set_message("Save Aborted".into());
+                    return Ok(());
+                }
             }
-            KeyEvent {
-                code: KeyCode::Char('f'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
+        }
+        match self.output.editor_rows.save() {
+            Ok(len) => {
+                self.output
+                    .status_message
+                    .set_message(format!("{} bytes written to disk", len));
+                self.output.dirty = 0;
+                self.output.history.mark_saved()
+            }
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Can't save! I/O error: {}", err)),
+        }
+        Ok(())
+    }
+
+    /// Runs `command`, returning whether the editor should keep going
+    fn execute(&mut self, command: Command) -> crossterm::Result<bool> {
+        let previous = self.last_command.replace(command);
+        let killing = matches!(
+            previous,
+            Some(Command::KillLine | Command::KillRegion | Command::CopyRegion)
+        );
+        let movement = |command| match command {
+            Command::MoveUp | Command::SelectUp => Some(KeyCode::Up),
+            Command::MoveDown | Command::SelectDown => Some(KeyCode::Down),
+            Command::MoveLeft | Command::SelectLeft => Some(KeyCode::Left),
+            Command::MoveRight | Command::SelectRight => Some(KeyCode::Right),
+            Command::MoveHome | Command::SelectHome => Some(KeyCode::Home),
+            Command::MoveEnd | Command::SelectEnd => Some(KeyCode::End),
+            Command::PageUp | Command::SelectPageUp => Some(KeyCode::PageUp),
+            Command::PageDown | Command::SelectPageDown => Some(KeyCode::PageDown),
+            _ => None,
+        };
+        if let Some(direction) = movement(command) {
+            let selecting = command.is_selection()
+                || (self.mark && self.output.cursor_controller.anchor.is_some());
+            if matches!(direction, KeyCode::PageUp | KeyCode::PageDown) {
+                self.output.move_page(direction, selecting)
+            } else {
+                self.output.history.seal();
+                self.output.cursor_controller.update_anchor(selecting);
+                self.output.move_cursor(direction)
+            }
+        }
+        match command {
+            Command::Quit => return Ok(!self.quit()),
+            Command::Palette => return self.command_line(),
+            Command::Save => self.save()?,
+            Command::Find => {
+                self.output.history.seal();
                 self.output.find()?;
             }
-            KeyEvent {
-                code: key @ (KeyCode::Backspace | KeyCode::Delete),
-                modifiers: KeyModifiers::NONE,
-            } => {
-                if matches!(key, KeyCode::Delete) {
+            Command::Replace => {
+                self.output.history.seal();
+                self.output.replace()?;
+            }
+            Command::OpenFile => self.output.open_file()?,
+            Command::GoToLine => self.output.go_to_line()?,
+            Command::ChooseSyntax => self.output.choose_syntax()?,
+            Command::ListBuffers => self.output.list_buffers(),
+            Command::NextBuffer => self.output.cycle_buffer(true),
+            Command::PreviousBuffer => self.output.cycle_buffer(false),
+            Command::SplitHorizontal => self.output.split_window(false),
+            Command::SplitVertical => self.output.split_window(true),
+            Command::NextWindow => self.output.next_window(),
+            Command::CloseWindow => self.output.close_window(),
+            Command::ToggleReadOnly => self.output.toggle_read_only(),
+            Command::CycleLineNumbers => self.output.cycle_line_numbers(),
+            Command::LineEndingsLf => self.output.set_line_ending(LineEnding::Lf),
+            Command::LineEndingsCrlf => self.output.set_line_ending(LineEnding::CrLf),
+            Command::Undo => self.output.undo(),
+            Command::Redo => self.output.redo(),
+            Command::Copy => self.output.copy(),
+            Command::Cut => self.output.cut(),
+            Command::Paste => {
+                self.output.history.seal();
+                self.output.paste()
+            }
+            Command::DeleteBackward | Command::DeleteForward => {
+                if command == Command::DeleteForward && self.output.selection().is_none() {
                     self.output.move_cursor(KeyCode::Right)
                 }
                 self.output.delete_char()
             }
-            KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: KeyModifiers::NONE,
-            } => self.output.insert_newline(),
-            KeyEvent {
-                code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
-            } => self.output.insert_char(match code {
-                KeyCode::Tab => '\t',
-                KeyCode::Char(ch) => ch,
-                _ => unreachable!(),
-            }),
+            Command::InsertNewline => self.output.insert_newline(),
+            Command::InsertTab => self.output.insert_tab(),
+            Command::DescribeKey => self.describe_key()?,
+            Command::ToggleVimMode => self.toggle_vim(),
+            Command::SetMark => {
+                self.mark = true;
+                self.output.cursor_controller.anchor =
+                    Some(self.output.cursor_controller.position());
+                self.output.status_message.set_message("Mark set".into())
+            }
+            Command::Cancel => {
+                self.output.cursor_controller.anchor = None;
+                self.output.status_message.set_message("Quit".into())
+            }
+            Command::KillLine => self.output.kill_line(killing),
+            Command::KillRegion => self.output.kill_region(killing),
+            Command::CopyRegion => self.output.copy_region(killing),
+            Command::Yank => self.output.yank(),
+            Command::YankPop => {
+                if matches!(previous, Some(Command::Yank | Command::YankPop)) {
+                    self.output.yank_pop()
+                } else {
+                    self.output
+                        .status_message
+                        .set_message("Previous command was not a yank".into())
+                }
+            }
             _ => {}
         }
-        self.quit_times = QUIT_TIMES;
+        self.mark &= self.output.cursor_controller.anchor.is_some();
+        self.quit_times = self.output.config.quit_times;
         Ok(true)
     }
 
     fn run(&mut self) -> crossterm::Result<bool> {
+        self.update_vim();
         self.output.refresh_screen()?;
         self.process_keypress()
     }
 }
 
 fn main() -> crossterm::Result<()> {
+    let usage_error = |message: String| -> ! {
+        eprintln!("pound: {}\n\n{}", message, cli::USAGE);
+        process::exit(2)
+    };
+    let mut args = match cli::parse(env::args().skip(1)) {
+        Ok(cli::Command::Edit(args)) => args,
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(cli::Command::Version) => {
+            println!("pound {}", VERSION);
+            return Ok(());
+        }
+        Err(message) => usage_error(message),
+    };
+    if let Some(name) = &args.syntax {
+        if Output::syntax_by_name(name).is_none() {
+            usage_error(format!("unknown syntax {}", name))
+        }
+    }
+    /* with nothing to open and input coming from a pipe, act like `less` */
+    if args.files.is_empty() && !io::stdin().is_terminal() {
+        args.files.push(FileArg {
+            source: Source::Stdin,
+            location: None,
+        });
+        args.pager = true
+    }
+    /* read before raw mode so that typing into a terminal ends with Ctrl-D as usual */
+    let stdin = if args.reads_stdin() {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        Some(bytes)
+    } else {
+        None
+    };
     let _clean_up = CleanUp;
     terminal::enable_raw_mode()?;
-    let mut editor = Editor::new();
+    let mut editor = Editor::new(&args, stdin);
     while editor.run()? {}
     Ok(())
 }
@@ -0,0 +1,253 @@
+//! What colours the terminal can show, and the nearest it has to the ones a theme asks for
+
+use crossterm::queue;
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
+use std::env;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// No colour at all, for `NO_COLOR` and dumb terminals
+    Monochrome,
+    Ansi16,
+    Ansi256,
+    #[default]
+    TrueColor,
+}
+
+/// The 16 colours by their index, with the RGB values xterm gives them by default
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The steps of each channel in the 6×6×6 cube at 256-colour indices 16 to 231
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The RGB value of a 256-colour index
+fn index_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE[(index / 36) as usize],
+                CUBE[(index / 6 % 6) as usize],
+                CUBE[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn distance((r, g, b): (u8, u8, u8), (other_r, other_g, other_b): (u8, u8, u8)) -> u32 {
+    [(r, other_r), (g, other_g), (b, other_b)]
+        .iter()
+        .map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u32)
+        .sum()
+}
+
+/// The index in `indices` whose colour is closest to `rgb`
+fn nearest(rgb: (u8, u8, u8), indices: impl Iterator<Item = u8>) -> u8 {
+    indices
+        .min_by_key(|index| distance(rgb, index_rgb(*index)))
+        .unwrap_or_default()
+}
+
+impl ColorDepth {
+    /// Goes by `NO_COLOR`, which turns colour off when set to anything but an empty string,
+    /// then `COLORTERM` and `TERM`, since terminfo has no reliable word on true colour
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Self::Monochrome;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if term == "dumb" {
+            Self::Monochrome
+        } else if ["truecolor", "24bit"].contains(&colorterm.as_str()) || term.ends_with("-direct")
+        {
+            Self::TrueColor
+        } else if term.contains("256") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+
+    /// Reads the `color_depth` setting, where `None` means it is to be detected
+    pub fn from_name(name: &str) -> Option<Option<Self>> {
+        let depth = match name {
+            "auto" => None,
+            "none" => Some(Self::Monochrome),
+            "16" => Some(Self::Ansi16),
+            "256" => Some(Self::Ansi256),
+            "truecolor" => Some(Self::TrueColor),
+            _ => return None,
+        };
+        Some(depth)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Monochrome => "no colours",
+            Self::Ansi16 => "16 colours",
+            Self::Ansi256 => "256 colours",
+            Self::TrueColor => "true colour",
+        }
+    }
+
+    /// The closest colour to `color` the terminal can show, or `None` without colours
+    pub fn fit(self, color: Color) -> Option<Color> {
+        let fitted = match (self, color) {
+            (Self::Monochrome, _) => return None,
+            (Self::TrueColor, _) | (_, Color::Reset) => color,
+            (Self::Ansi256, Color::Rgb { r, g, b }) => {
+                /* the first 16 are left out, as terminals are often set up with other values */
+                Color::AnsiValue(nearest((r, g, b), 16..=255))
+            }
+            (Self::Ansi256, _) => color,
+            (Self::Ansi16, Color::Rgb { r, g, b }) => ANSI[nearest((r, g, b), 0..16) as usize].0,
+            (Self::Ansi16, Color::AnsiValue(index)) => {
+                ANSI[nearest(index_rgb(index), 0..16) as usize].0
+            }
+            (Self::Ansi16, _) => color,
+        };
+        Some(fitted)
+    }
+
+    /// Queues a change of colour. With only 16 colours these are sent as the original SGR
+    /// codes rather than as indices into a 256-colour palette the terminal may not have
+    pub fn queue_color(
+        self,
+        color: Color,
+        background: bool,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let index = ANSI.iter().position(|(named, _)| *named == color);
+        match index {
+            Some(index) if self == Self::Ansi16 => {
+                let base = match (background, index < 8) {
+                    (false, true) => 30,
+                    (false, false) => 90 - 8,
+                    (true, true) => 40,
+                    (true, false) => 100 - 8,
+                };
+                write!(out, "\x1b[{}m", base + index)
+            }
+            _ if background => queue!(out, SetBackgroundColor(color)),
+            _ => queue!(out, SetForegroundColor(color)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPTHS: [ColorDepth; 4] = [
+        ColorDepth::Monochrome,
+        ColorDepth::Ansi16,
+        ColorDepth::Ansi256,
+        ColorDepth::TrueColor,
+    ];
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::Rgb { r, g, b }
+    }
+
+    #[test]
+    fn fits_nothing_without_colours() {
+        for color in [Color::Reset, Color::Red, Color::AnsiValue(42), rgb(1, 2, 3)] {
+            assert_eq!(ColorDepth::Monochrome.fit(color), None);
+        }
+    }
+
+    #[test]
+    fn keeps_colours_the_terminal_has() {
+        for depth in &DEPTHS[1..] {
+            assert_eq!(depth.fit(Color::Reset), Some(Color::Reset));
+            assert_eq!(depth.fit(Color::DarkCyan), Some(Color::DarkCyan));
+        }
+        let color = rgb(12, 34, 56);
+        assert_eq!(ColorDepth::TrueColor.fit(color), Some(color));
+        let color = Color::AnsiValue(99);
+        assert_eq!(ColorDepth::Ansi256.fit(color), Some(color));
+    }
+
+    #[test]
+    fn fits_rgb_to_the_256_colour_cube_and_grey_ramp() {
+        assert_eq!(
+            ColorDepth::Ansi256.fit(rgb(255, 0, 0)),
+            Some(Color::AnsiValue(196))
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.fit(rgb(90, 140, 210)),
+            Some(Color::AnsiValue(68))
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.fit(rgb(128, 128, 128)),
+            Some(Color::AnsiValue(244))
+        );
+        /* never one of the first 16, even for an exact match */
+        assert_eq!(
+            ColorDepth::Ansi256.fit(rgb(205, 0, 0)),
+            Some(Color::AnsiValue(160))
+        );
+    }
+
+    #[test]
+    fn fits_rgb_and_indices_to_16_colours() {
+        assert_eq!(ColorDepth::Ansi16.fit(rgb(250, 10, 10)), Some(Color::Red));
+        assert_eq!(ColorDepth::Ansi16.fit(rgb(20, 20, 20)), Some(Color::Black));
+        assert_eq!(
+            ColorDepth::Ansi16.fit(Color::AnsiValue(3)),
+            Some(Color::DarkYellow)
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.fit(Color::AnsiValue(196)),
+            Some(Color::Red)
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.fit(Color::AnsiValue(244)),
+            Some(Color::DarkGrey)
+        );
+    }
+
+    #[test]
+    fn reads_depth_names() {
+        assert!(ColorDepth::from_name("auto") == Some(None));
+        assert!(ColorDepth::from_name("none") == Some(Some(ColorDepth::Monochrome)));
+        assert!(ColorDepth::from_name("256") == Some(Some(ColorDepth::Ansi256)));
+        assert!(ColorDepth::from_name("88").is_none());
+    }
+
+    #[test]
+    fn sends_16_colours_as_sgr_codes() {
+        let mut out = Vec::new();
+        ColorDepth::Ansi16
+            .queue_color(Color::DarkRed, false, &mut out)
+            .unwrap();
+        ColorDepth::Ansi16
+            .queue_color(Color::Cyan, true, &mut out)
+            .unwrap();
+        assert_eq!(out, b"\x1b[31m\x1b[106m");
+    }
+}
@@ -0,0 +1,356 @@
+//! Highlighting for Rust, which needs more than a syntax definition offers: lifetimes next
+//! to char literals, raw strings, nested block comments, doc comments, attributes and macros
+
+use crate::syntax::Continuation;
+use crate::{HighlightType, Row, SyntaxHighlight};
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "union", "unsafe",
+    "use", "where", "while",
+];
+
+/// The numeric types, which number literals may also end with
+const NUMBER_TYPES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32",
+    "f64",
+];
+
+const OTHER_TYPES: &[&str] = &["char", "str", "bool"];
+
+fn is_identifier(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+/// Length of the number literal at the start of `text`, suffix included
+fn number(text: &[u8]) -> Option<usize> {
+    let digits = |from: usize, is_digit: fn(&u8) -> bool| {
+        from + text[from..]
+            .iter()
+            .take_while(|c| is_digit(c) || **c == b'_')
+            .count()
+    };
+    let radix: Option<fn(&u8) -> bool> = match text.get(..2) {
+        Some(b"0x") => Some(u8::is_ascii_hexdigit),
+        Some(b"0o") => Some(|c| (b'0'..=b'7').contains(c)),
+        Some(b"0b") => Some(|c| matches!(c, b'0' | b'1')),
+        _ => None,
+    };
+    let mut end = match radix {
+        Some(is_digit) => Some(digits(2, is_digit)).filter(|end| *end > 2)?,
+        None if text.first()?.is_ascii_digit() => {
+            let mut end = digits(0, u8::is_ascii_digit);
+            /* `1.5` but neither `1..5` nor `1.max(2)` */
+            if text.get(end) == Some(&b'.') && text.get(end + 1).is_some_and(u8::is_ascii_digit) {
+                end = digits(end + 1, u8::is_ascii_digit)
+            }
+            if matches!(text.get(end), Some(b'e' | b'E')) {
+                let sign = usize::from(matches!(text.get(end + 1), Some(b'+' | b'-')));
+                if text.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                    end = digits(end + 1 + sign, u8::is_ascii_digit)
+                }
+            }
+            end
+        }
+        None => return None,
+    };
+    if let Some(suffix) = NUMBER_TYPES
+        .iter()
+        .find(|suffix| text[end..].starts_with(suffix.as_bytes()))
+    {
+        end += suffix.len()
+    }
+    Some(end)
+}
+
+/// Where the string or char literal starting at `from` ends, past its closing `delimiter`.
+/// Backslashes escape the character after them unless the literal is raw
+fn close(text: &[u8], from: usize, delimiter: &[u8], raw: bool) -> Option<usize> {
+    let mut end = from;
+    while end < text.len() {
+        if text[end..].starts_with(delimiter) {
+            return Some(end + delimiter.len());
+        }
+        end += if text[end] == b'\\' && !raw { 2 } else { 1 }
+    }
+    None
+}
+
+/// The closing delimiter of the raw string whose prefix (`r`, `br` or `cr`) ends just
+/// before `text`, along with the length of the `#`s and `"` opening it
+fn raw_string(text: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let hashes = text.iter().take_while(|c| **c == b'#').count();
+    if text.get(hashes) != Some(&b'"') {
+        return None;
+    }
+    let mut delimiter = vec![b'"'];
+    delimiter.resize(hashes + 1, b'#');
+    Some((delimiter, hashes + 1))
+}
+
+/// What an identifier is, going by the words Rust reserves and its naming conventions: a
+/// `CamelCase` type, a `SCREAMING_CASE` constant and a function when called
+fn identifier(word: &str, called: bool) -> HighlightType {
+    if KEYWORDS.contains(&word) {
+        HighlightType::Keyword
+    } else if word == "true" || word == "false" {
+        HighlightType::Constant
+    } else if NUMBER_TYPES.contains(&word) || OTHER_TYPES.contains(&word) {
+        HighlightType::Type
+    } else if called {
+        HighlightType::Function
+    } else if !word.starts_with(|c: char| c.is_ascii_uppercase()) {
+        HighlightType::Normal
+    } else if word.len() > 1 && !word.contains(|c: char| c.is_ascii_lowercase()) {
+        HighlightType::Constant
+    } else {
+        HighlightType::Type
+    }
+}
+
+/// Length of the char literal at the start of `text`, or `None` for a lifetime or label
+fn char_literal(text: &[u8]) -> Option<usize> {
+    match text.get(1)? {
+        b'\\' => close(text, 1, b"'", false),
+        b'\'' => None,
+        first => {
+            let width = match first {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            (text.get(1 + width) == Some(&b'\'')).then_some(width + 2)
+        }
+    }
+}
+
+pub struct RustHighlight;
+
+impl SyntaxHighlight for RustHighlight {
+    fn extensions(&self) -> &[&str] {
+        &["rs"]
+    }
+
+    fn shebangs(&self) -> &[&str] {
+        &["rust-script"]
+    }
+
+    fn file_type(&self) -> &str {
+        "rust"
+    }
+
+    fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>) {
+        /* a change to what a row leaves open carries on to the next */
+        for at in at..editor_rows.len() {
+            let mut open = at
+                .checked_sub(1)
+                .and_then(|previous| editor_rows[previous].continuation.clone());
+            let row = &mut editor_rows[at];
+            let render = row.render.as_bytes();
+            let starts = |i: usize, token: &[u8]| render[i..].starts_with(token);
+            let mut highlight = Vec::with_capacity(render.len());
+            let mut add = |highlight_type: HighlightType, length: usize| {
+                (0..length).for_each(|_| highlight.push(highlight_type))
+            };
+            let mut i = 0;
+            /* how deep in the brackets of an attribute the text is */
+            let mut attribute = 0;
+            if at == 0 && starts(0, b"#!") && !starts(0, b"#![") {
+                add(HighlightType::Comment, render.len());
+                i = render.len()
+            }
+            while i < render.len() {
+                match &open {
+                    Some(Continuation::Comment { depth, doc }) => {
+                        let highlight_type = match doc {
+                            true => HighlightType::DocComment,
+                            false => HighlightType::MultilineComment,
+                        };
+                        let (depth, length) = match (starts(i, b"/*"), starts(i, b"*/")) {
+                            (true, _) => (depth + 1, 2),
+                            (_, true) => (depth - 1, 2),
+                            _ => (*depth, 1),
+                        };
+                        let doc = *doc;
+                        add(highlight_type, length);
+                        i += length;
+                        open = (depth > 0).then_some(Continuation::Comment { depth, doc });
+                        continue;
+                    }
+                    Some(Continuation::Delimiter(delimiter) | Continuation::Raw(delimiter)) => {
+                        let raw = matches!(open, Some(Continuation::Raw(_)));
+                        let end = match close(render, i, delimiter.as_bytes(), raw) {
+                            Some(end) => {
+                                open = None;
+                                end
+                            }
+                            None => render.len(),
+                        };
+                        add(HighlightType::String, end - i);
+                        i = end;
+                        continue;
+                    }
+                    _ => {}
+                }
+                let previous_separator = i == 0 || !is_identifier(render[i - 1]);
+                if starts(i, b"//") {
+                    /* `///` and `//!` document, but `////` is a plain comment again */
+                    let doc = (starts(i, b"///") && !starts(i, b"////")) || starts(i, b"//!");
+                    let highlight_type = match doc {
+                        true => HighlightType::DocComment,
+                        false => HighlightType::Comment,
+                    };
+                    add(highlight_type, render.len() - i);
+                    break;
+                }
+                if starts(i, b"/*") {
+                    let doc = (starts(i, b"/**") && !starts(i, b"/***") && !starts(i, b"/**/"))
+                        || starts(i, b"/*!");
+                    open = Some(Continuation::Comment { depth: 1, doc });
+                    add(
+                        match doc {
+                            true => HighlightType::DocComment,
+                            false => HighlightType::MultilineComment,
+                        },
+                        2,
+                    );
+                    i += 2;
+                    continue;
+                }
+                if previous_separator {
+                    let prefix = [&b"br"[..], b"cr", b"r", b"b", b"c"]
+                        .into_iter()
+                        .find(|prefix| starts(i, prefix))
+                        .map_or(0, |prefix| prefix.len());
+                    let raw = match prefix > 0 && render[i + prefix - 1] == b'r' {
+                        true => raw_string(&render[i + prefix..]),
+                        false => None,
+                    };
+                    if let Some((delimiter, opening)) = raw {
+                        let from = i + prefix + opening;
+                        let end = close(render, from, &delimiter, true).unwrap_or_else(|| {
+                            open = Some(Continuation::Raw(
+                                String::from_utf8_lossy(&delimiter).into(),
+                            ));
+                            render.len()
+                        });
+                        add(HighlightType::String, end - i);
+                        i = end;
+                        continue;
+                    }
+                    let quoted = |prefix: usize| render.get(i + prefix).copied();
+                    if (prefix == 0 || render[i] != b'r') && quoted(prefix) == Some(b'"') {
+                        let end =
+                            close(render, i + prefix + 1, b"\"", false).unwrap_or_else(|| {
+                                open = Some(Continuation::Delimiter("\"".into()));
+                                render.len()
+                            });
+                        add(HighlightType::String, end - i);
+                        i = end;
+                        continue;
+                    }
+                    let byte = usize::from(starts(i, b"b'"));
+                    if render[i + byte] == b'\'' {
+                        let (highlight_type, length) = match char_literal(&render[i + byte..]) {
+                            Some(length) => (HighlightType::CharLiteral, byte + length),
+                            /* a lifetime or a loop label */
+                            None if byte == 0 => {
+                                let length = 1 + render[i + 1..]
+                                    .iter()
+                                    .take_while(|c| is_identifier(**c))
+                                    .count();
+                                (
+                                    if length > 1 {
+                                        HighlightType::Type
+                                    } else {
+                                        HighlightType::Error
+                                    },
+                                    length,
+                                )
+                            }
+                            None => (HighlightType::Error, byte + 1),
+                        };
+                        add(highlight_type, length);
+                        i += length;
+                        continue;
+                    }
+                }
+                if starts(i, b"#[") || starts(i, b"#![") {
+                    let length = if render[i + 1] == b'!' { 3 } else { 2 };
+                    add(HighlightType::Attribute, length);
+                    i += length;
+                    attribute = 1;
+                    continue;
+                }
+                if attribute > 0 {
+                    match render[i] {
+                        b'[' => attribute += 1,
+                        b']' => attribute -= 1,
+                        _ => {}
+                    }
+                    add(HighlightType::Attribute, 1);
+                    i += 1;
+                    continue;
+                }
+                if previous_separator {
+                    if let Some(length) = number(&render[i..]) {
+                        if render.get(i + length).is_none_or(|c| !is_identifier(*c)) {
+                            add(HighlightType::Number, length);
+                            i += length;
+                            continue;
+                        }
+                    }
+                    if is_identifier(render[i]) {
+                        /* `r#type` is an identifier that happens to be spelt like a keyword */
+                        let start = if starts(i, b"r#") { i + 2 } else { i };
+                        let end = start
+                            + render[start..]
+                                .iter()
+                                .take_while(|c| is_identifier(**c))
+                                .count();
+                        let is_macro = starts(end, b"!") && !starts(end, b"!=");
+                        let highlight_type = if is_macro {
+                            HighlightType::Function
+                        } else if start > i {
+                            match starts(end, b"(") {
+                                true => HighlightType::Function,
+                                false => HighlightType::Normal,
+                            }
+                        } else {
+                            identifier(&row.render[start..end], starts(end, b"("))
+                        };
+                        let end = end + usize::from(is_macro);
+                        add(highlight_type, end - i);
+                        i = end;
+                        continue;
+                    }
+                }
+                if starts(i, b"*/") {
+                    add(HighlightType::Error, 2);
+                    i += 2;
+                    continue;
+                }
+                add(
+                    match render[i] {
+                        b'+' | b'-' | b'*' | b'/' | b'%' | b'=' | b'<' | b'>' | b'!' | b'&'
+                        | b'|' | b'^' | b'?' => HighlightType::Operator,
+                        b'(' | b')' | b'[' | b']' | b'{' | b'}' | b',' | b';' | b':' | b'.' => {
+                            HighlightType::Punctuation
+                        }
+                        _ => HighlightType::Normal,
+                    },
+                    1,
+                );
+                i += 1;
+            }
+            row.highlight = highlight;
+            let changed = row.continuation != open;
+            row.continuation = open;
+            if !changed {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,645 @@
+//! Syntax definitions read from `$XDG_CONFIG_HOME/pound/syntax/*.toml` (or `.json`, with
+//! the same keys), so that languages can be added without recompiling:
+//!
+//! ```toml
+//! name = "python"                   # the file type shown in the status bar
+//! extensions = ["py", "pyw"]
+//! aliases = ["python3"]             # other names for it in modelines and `:set ft=`
+//! filenames = ["SConstruct"]        # whole file names, for files without an extension
+//! globs = ["*.pythonrc"]            # `*` and `?` patterns, against the whole path with a `/`
+//! shebangs = ["python", "python3"]  # interpreters named on a `#!` first line
+//! line_comment = "#"
+//! block_comment = ["/*", "*/"]
+//! strings = ["\"", "'"]             # delimiters; a backslash escapes the next character
+//! multiline_strings = ["\"\"\""]    # delimiters of strings that may span rows
+//! heredocs = false                  # `<<EOF` starts a string ending at a row `EOF`
+//! comment_after_blank = false       # the line comment needs a blank or row start before it
+//!
+//! [numbers]                         # or `numbers = false` to leave them uncoloured
+//! prefixes = ["0x", "0o", "0b"]     # radix prefixes, followed by hex digits
+//! separator = "_"                   # allowed between digits
+//! suffixes = ["j"]                  # allowed straight after a number
+//!
+//! [[keywords]]                      # any number of groups, each with its colour
+//! color = "keyword"                 # a theme class such as "type", or a colour
+//! words = ["def", "class", "return"]
+//!
+//! [[patterns]]                      # regular expressions matched against each row;
+//! regex = '^\s*(@[\w.]+)'           # with a group, only the first one is coloured
+//! color = "attribute"
+//! ```
+//!
+//! A definition with the same name or extension as a bundled one, from the `syntax`
+//! directory of the source tree, takes its place.
+
+use crate::config::{parse_color, parse_toml, Config};
+use crate::{theme, HighlightType, Row, SyntaxHighlight};
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::{fs, io};
+use toml::{Table, Value};
+
+/// Definitions that come with the editor
+const BUNDLED: &[(&str, &str)] = &[
+    ("c.toml", include_str!("../syntax/c.toml")),
+    ("cpp.toml", include_str!("../syntax/cpp.toml")),
+    ("python.toml", include_str!("../syntax/python.toml")),
+    ("javascript.toml", include_str!("../syntax/javascript.toml")),
+    ("typescript.toml", include_str!("../syntax/typescript.toml")),
+    ("go.toml", include_str!("../syntax/go.toml")),
+    ("toml.toml", include_str!("../syntax/toml.toml")),
+    ("json.toml", include_str!("../syntax/json.toml")),
+    ("yaml.toml", include_str!("../syntax/yaml.toml")),
+    ("markdown.toml", include_str!("../syntax/markdown.toml")),
+    ("shell.toml", include_str!("../syntax/shell.toml")),
+    ("makefile.toml", include_str!("../syntax/makefile.toml")),
+    ("dockerfile.toml", include_str!("../syntax/dockerfile.toml")),
+];
+
+/// A string still open at the end of a row, which the rows after it carry on
+#[derive(Clone, PartialEq, Eq)]
+pub enum Continuation {
+    /// Ends at the next `delimiter`, as a `"""` string does
+    Delimiter(String),
+    /// Ends after a row holding only `terminator`, which `<<-` lets be indented
+    Heredoc { terminator: String, indented: bool },
+    /// Like `Delimiter`, but backslashes escape nothing, as in Rust's `r#"..."#`
+    Raw(String),
+    /// A block comment, in Rust's case with others nested `depth` deep inside it
+    Comment { depth: usize, doc: bool },
+}
+
+/// How numbers are recognised. Decimals, fractions and exponents always are
+pub struct NumberRules {
+    enabled: bool,
+    prefixes: Vec<String>,
+    separator: Option<char>,
+    suffixes: Vec<String>,
+}
+
+impl Default for NumberRules {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefixes: Vec::new(),
+            separator: None,
+            suffixes: Vec::new(),
+        }
+    }
+}
+
+impl NumberRules {
+    fn parse(value: &Value) -> Result<Self, String> {
+        let mut rules = Self::default();
+        if let Some(enabled) = value.as_bool() {
+            rules.enabled = enabled;
+            return Ok(rules);
+        }
+        let table = value
+            .as_table()
+            .ok_or("numbers must be true, false or a table")?;
+        for (key, value) in table {
+            let key = format!("numbers.{}", key);
+            match key.as_str() {
+                "numbers.prefixes" => rules.prefixes = list(&key, value)?,
+                "numbers.suffixes" => rules.suffixes = list(&key, value)?,
+                "numbers.separator" => {
+                    let mut chars = string(&key, value)?.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(separator), None) => rules.separator = Some(separator),
+                        _ => return Err(format!("{} must be a single character", key)),
+                    }
+                }
+                _ => return Err(format!("{} is not a known key", key)),
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Length of the number at the start of `text`, if it starts with one
+    fn scan(&self, text: &[u8]) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        let digits = |from: usize, radix: u32| {
+            let mut end = from;
+            while end < text.len()
+                && ((text[end] as char).is_digit(radix)
+                    || (end > from
+                        && self.separator == Some(text[end] as char)
+                        && text
+                            .get(end + 1)
+                            .is_some_and(|next| (*next as char).is_digit(radix))))
+            {
+                end += 1
+            }
+            end
+        };
+        let prefix = self.prefixes.iter().find(|prefix| {
+            text.len() > prefix.len()
+                && text[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+                && text[prefix.len()].is_ascii_hexdigit()
+        });
+        let mut end = match prefix {
+            Some(prefix) => digits(prefix.len(), 16),
+            None if text.first()?.is_ascii_digit() => {
+                let mut end = digits(0, 10);
+                if text.get(end) == Some(&b'.') && text.get(end + 1).is_some_and(u8::is_ascii_digit)
+                {
+                    end = digits(end + 1, 10)
+                }
+                if matches!(text.get(end), Some(b'e' | b'E')) {
+                    let sign = matches!(text.get(end + 1), Some(b'+' | b'-')) as usize;
+                    if text.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                        end = digits(end + 1 + sign, 10)
+                    }
+                }
+                end
+            }
+            None => return None,
+        };
+        if let Some(suffix) = self
+            .suffixes
+            .iter()
+            .filter(|suffix| text[end..].starts_with(suffix.as_bytes()))
+            .max_by_key(|suffix| suffix.len())
+        {
+            end += suffix.len()
+        }
+        Some(end)
+    }
+}
+
+pub struct SyntaxDefinition {
+    name: String,
+    aliases: Vec<String>,
+    extensions: Vec<String>,
+    filenames: Vec<String>,
+    globs: Vec<String>,
+    shebangs: Vec<String>,
+    line_comment: Option<String>,
+    comment_after_blank: bool,
+    block_comment: Option<(String, String)>,
+    strings: Vec<String>,
+    multiline_strings: Vec<String>,
+    heredocs: bool,
+    keywords: Vec<(HighlightType, Vec<String>)>,
+    patterns: Vec<(Regex, HighlightType)>,
+    numbers: NumberRules,
+}
+
+/// Reads the colour of a keyword group or pattern, which is better given as a class for
+/// the theme to style
+fn highlight_type(value: &Value) -> Option<HighlightType> {
+    value
+        .as_str()
+        .and_then(theme::class)
+        .or_else(|| parse_color(value).map(HighlightType::Other))
+}
+
+/// Length of the `<<EOF`, `<<-EOF` or `<<'EOF'` at the start of `text`, with its
+/// terminator and whether that may be indented
+fn heredoc_start(text: &[u8]) -> Option<(usize, String, bool)> {
+    let indented = text.get(2) == Some(&b'-');
+    let mut end = 2 + indented as usize;
+    while text.get(end) == Some(&b' ') {
+        end += 1
+    }
+    let quote = text.get(end).copied().filter(|c| *c == b'\'' || *c == b'"');
+    end += quote.is_some() as usize;
+    let start = end;
+    while text
+        .get(end)
+        .is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_')
+    {
+        end += 1
+    }
+    if end == start
+        || text[start].is_ascii_digit()
+        || quote.is_some_and(|quote| text.get(end) != Some(&quote))
+    {
+        return None;
+    }
+    let terminator = String::from_utf8_lossy(&text[start..end]).into_owned();
+    Some((end + quote.is_some() as usize, terminator, indented))
+}
+
+fn string<'a>(key: &str, value: &'a Value) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .filter(|text| !text.is_empty())
+        .ok_or_else(|| format!("{} must be a non-empty string", key))
+}
+
+fn list(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    value
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .filter(|text| !text.is_empty())
+                        .map(String::from)
+                })
+                .collect()
+        })
+        .ok_or_else(|| format!("{} must be a list of non-empty strings", key))
+}
+
+impl SyntaxDefinition {
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::from_text(&text, path.extension().is_some_and(|ext| ext == "json"))
+    }
+
+    fn from_text(text: &str, json: bool) -> Result<Self, String> {
+        let table = if json {
+            serde_json::from_str::<Table>(text).map_err(|err| err.to_string())?
+        } else {
+            parse_toml(text)?
+        };
+        Self::parse(&table)
+    }
+
+    pub fn parse(table: &Table) -> Result<Self, String> {
+        let mut definition = Self {
+            name: String::new(),
+            aliases: Vec::new(),
+            extensions: Vec::new(),
+            filenames: Vec::new(),
+            globs: Vec::new(),
+            shebangs: Vec::new(),
+            line_comment: None,
+            comment_after_blank: false,
+            block_comment: None,
+            strings: Vec::new(),
+            multiline_strings: Vec::new(),
+            heredocs: false,
+            keywords: Vec::new(),
+            patterns: Vec::new(),
+            numbers: NumberRules::default(),
+        };
+        let flag = |key: &str, value: &Value| {
+            value
+                .as_bool()
+                .ok_or_else(|| format!("{} must be true or false", key))
+        };
+        for (key, value) in table {
+            match key.as_str() {
+                "name" => definition.name = string(key, value)?.to_string(),
+                "aliases" => definition.aliases = list(key, value)?,
+                "extensions" => definition.extensions = list(key, value)?,
+                "filenames" => definition.filenames = list(key, value)?,
+                "globs" => definition.globs = list(key, value)?,
+                "shebangs" => definition.shebangs = list(key, value)?,
+                "line_comment" => definition.line_comment = Some(string(key, value)?.to_string()),
+                "block_comment" => {
+                    let mut tokens = list(key, value)?;
+                    if tokens.len() != 2 {
+                        return Err(format!("{} must be a start and an end token", key));
+                    }
+                    let end = tokens.pop().unwrap();
+                    definition.block_comment = Some((tokens.pop().unwrap(), end))
+                }
+                "comment_after_blank" => definition.comment_after_blank = flag(key, value)?,
+                "strings" => definition.strings = list(key, value)?,
+                "multiline_strings" => definition.multiline_strings = list(key, value)?,
+                "heredocs" => definition.heredocs = flag(key, value)?,
+                "numbers" => definition.numbers = NumberRules::parse(value)?,
+                "keywords" => {
+                    let groups = value
+                        .as_array()
+                        .ok_or("keywords must be a list of tables")?;
+                    for group in groups {
+                        let color = group
+                            .get("color")
+                            .and_then(highlight_type)
+                            .ok_or("keywords need a colour in `color`")?;
+                        let words = match group.get("words") {
+                            Some(words) => list("keywords.words", words)?,
+                            None => return Err("keywords need a list of `words`".into()),
+                        };
+                        definition.keywords.push((color, words))
+                    }
+                }
+                "patterns" => {
+                    let patterns = value
+                        .as_array()
+                        .ok_or("patterns must be a list of tables")?;
+                    for pattern in patterns {
+                        let color = pattern
+                            .get("color")
+                            .and_then(highlight_type)
+                            .ok_or("patterns need a colour in `color`")?;
+                        let regex = pattern
+                            .get("regex")
+                            .and_then(Value::as_str)
+                            .ok_or("patterns need a `regex`")?;
+                        let regex = Regex::new(regex).map_err(|err| {
+                            let err = err.to_string();
+                            format!("bad regex: {}", err.lines().last().unwrap_or_default())
+                        })?;
+                        definition.patterns.push((regex, color))
+                    }
+                }
+                _ => return Err(format!("{} is not a known key", key)),
+            }
+        }
+        if definition.name.is_empty() {
+            return Err("name is missing".into());
+        }
+        Ok(definition)
+    }
+}
+
+/// The definitions found in the config directory followed by the bundled ones, along with
+/// what was wrong with those that couldn't be used
+fn load() -> (Vec<SyntaxDefinition>, Vec<String>) {
+    let (mut definitions, mut notices) = load_directory();
+    for (name, text) in BUNDLED {
+        match SyntaxDefinition::from_text(text, false) {
+            Ok(definition) => definitions.push(definition),
+            Err(problem) => notices.push(format!("syntax: bundled {}: {}", name, problem)),
+        }
+    }
+    (definitions, notices)
+}
+
+fn load_directory() -> (Vec<SyntaxDefinition>, Vec<String>) {
+    let directory = match Config::directory() {
+        Some(directory) => directory.join("syntax"),
+        None => return (Vec::new(), Vec::new()),
+    };
+    let entries = match fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return (Vec::new(), Vec::new()),
+        Err(err) => {
+            let notice = format!("syntax: can't read {}: {}", directory.display(), err);
+            return (Vec::new(), vec![notice]);
+        }
+    };
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "toml" || ext == "json")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    let (mut definitions, mut notices) = (Vec::new(), Vec::new());
+    for path in paths {
+        match SyntaxDefinition::read(&path) {
+            Ok(definition) => definitions.push(definition),
+            Err(problem) => notices.push(format!(
+                "syntax: {}: {}",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                problem
+            )),
+        }
+    }
+    (definitions, notices)
+}
+
+static DEFINITIONS: OnceLock<(Vec<SyntaxDefinition>, Vec<String>)> = OnceLock::new();
+
+/// Problems with the definition files, for the status bar
+pub fn notices() -> &'static [String] {
+    &DEFINITIONS.get_or_init(load).1
+}
+
+pub fn highlighters() -> impl Iterator<Item = DefinedHighlight> {
+    DEFINITIONS
+        .get_or_init(load)
+        .0
+        .iter()
+        .map(DefinedHighlight::new)
+}
+
+/// A loaded definition as the editor sees every highlighter
+pub struct DefinedHighlight {
+    definition: &'static SyntaxDefinition,
+    aliases: Vec<&'static str>,
+    extensions: Vec<&'static str>,
+    filenames: Vec<&'static str>,
+    globs: Vec<&'static str>,
+    shebangs: Vec<&'static str>,
+}
+
+impl DefinedHighlight {
+    fn new(definition: &'static SyntaxDefinition) -> Self {
+        let strs = |list: &'static [String]| list.iter().map(String::as_str).collect();
+        Self {
+            definition,
+            aliases: strs(&definition.aliases),
+            extensions: strs(&definition.extensions),
+            filenames: strs(&definition.filenames),
+            globs: strs(&definition.globs),
+            shebangs: strs(&definition.shebangs),
+        }
+    }
+}
+
+impl SyntaxHighlight for DefinedHighlight {
+    fn extensions(&self) -> &[&str] {
+        &self.extensions
+    }
+
+    fn aliases(&self) -> &[&str] {
+        &self.aliases
+    }
+
+    fn filenames(&self) -> &[&str] {
+        &self.filenames
+    }
+
+    fn globs(&self) -> &[&str] {
+        &self.globs
+    }
+
+    fn shebangs(&self) -> &[&str] {
+        &self.shebangs
+    }
+
+    fn file_type(&self) -> &str {
+        &self.definition.name
+    }
+
+    fn is_separator(&self, c: char) -> bool {
+        !(c.is_alphanumeric() || c == '_')
+    }
+
+    fn update_syntax(&self, at: usize, editor_rows: &mut Vec<Row>) {
+        let definition = self.definition;
+        /* a change to what a row leaves open carries on to the next */
+        for at in at..editor_rows.len() {
+            let (mut in_comment, mut open) = match at.checked_sub(1) {
+                /* the flag may have been left by another highlighter */
+                Some(previous) => (
+                    editor_rows[previous].is_comment && definition.block_comment.is_some(),
+                    editor_rows[previous].continuation.clone(),
+                ),
+                None => (false, None),
+            };
+            let row = &mut editor_rows[at];
+            let render = row.render.as_bytes();
+            let starts = |i: usize, token: &str| render[i..].starts_with(token.as_bytes());
+            let is_boundary =
+                |i: usize| render.get(i).is_none_or(|c| self.is_separator(*c as char));
+            /* where a string starting before `from` ends, if it does on this row */
+            let close = |from: usize, delimiter: &str| {
+                let mut end = from;
+                while end < render.len() && !starts(end, delimiter) {
+                    end += if render[end] == b'\\' { 2 } else { 1 }
+                }
+                (end < render.len()).then_some(end + delimiter.len())
+            };
+            let spans = definition
+                .patterns
+                .iter()
+                .flat_map(|(regex, highlight_type)| {
+                    regex.captures_iter(&row.render).filter_map(|captures| {
+                        let span = captures.get(1).or_else(|| captures.get(0))?;
+                        (!span.is_empty()).then_some((span.start(), span.end(), *highlight_type))
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut highlight = Vec::with_capacity(render.len());
+            let mut add = |highlight_type: HighlightType, length: usize| {
+                (0..length).for_each(|_| highlight.push(highlight_type))
+            };
+            let mut i = 0;
+            let mut previous_separator = true;
+            let mut heredoc = None;
+            match &open {
+                Some(Continuation::Heredoc {
+                    terminator,
+                    indented,
+                }) => {
+                    let line = if *indented {
+                        row.render.trim_start()
+                    } else {
+                        &row.render
+                    };
+                    if line == terminator {
+                        open = None
+                    }
+                    add(HighlightType::String, render.len());
+                    i = render.len()
+                }
+                Some(Continuation::Delimiter(delimiter)) => {
+                    i = match close(0, delimiter) {
+                        Some(end) => {
+                            open = None;
+                            end
+                        }
+                        None => render.len(),
+                    };
+                    add(HighlightType::String, i)
+                }
+                _ => {}
+            }
+            while i < render.len() {
+                if let (true, Some((_, end))) = (in_comment, &definition.block_comment) {
+                    let length = if starts(i, end) {
+                        in_comment = false;
+                        previous_separator = true;
+                        end.len()
+                    } else {
+                        1
+                    };
+                    add(HighlightType::MultilineComment, length);
+                    i += length;
+                    continue;
+                }
+                if let Some(token) = &definition.line_comment {
+                    let after_blank = i == 0 || render[i - 1].is_ascii_whitespace();
+                    if starts(i, token) && (after_blank || !definition.comment_after_blank) {
+                        add(HighlightType::Comment, render.len() - i);
+                        break;
+                    }
+                }
+                if let Some((start, _)) = &definition.block_comment {
+                    if starts(i, start) {
+                        add(HighlightType::MultilineComment, start.len());
+                        i += start.len();
+                        in_comment = true;
+                        continue;
+                    }
+                }
+                if let Some(delimiter) =
+                    definition.multiline_strings.iter().find(|it| starts(i, it))
+                {
+                    let end = close(i + delimiter.len(), delimiter).unwrap_or_else(|| {
+                        open = Some(Continuation::Delimiter(delimiter.clone()));
+                        render.len()
+                    });
+                    add(HighlightType::String, end - i);
+                    i = end;
+                    previous_separator = true;
+                    continue;
+                }
+                if definition.heredocs && starts(i, "<<") && !starts(i, "<<<") {
+                    if let Some((length, terminator, indented)) = heredoc_start(&render[i..]) {
+                        add(HighlightType::String, length);
+                        i += length;
+                        heredoc = Some(Continuation::Heredoc {
+                            terminator,
+                            indented,
+                        });
+                        continue;
+                    }
+                }
+                if let Some((_, end, highlight_type)) = spans.iter().find(|span| span.0 == i) {
+                    add(*highlight_type, end - i);
+                    i = *end;
+                    previous_separator = true;
+                    continue;
+                }
+                if let Some(delimiter) = definition.strings.iter().find(|it| starts(i, it)) {
+                    let end = close(i + delimiter.len(), delimiter).unwrap_or(render.len());
+                    add(HighlightType::String, end - i);
+                    i = end;
+                    previous_separator = true;
+                    continue;
+                }
+                if previous_separator {
+                    let number = definition
+                        .numbers
+                        .scan(&render[i..])
+                        .filter(|length| is_boundary(i + length));
+                    if let Some(length) = number {
+                        add(HighlightType::Number, length);
+                        i += length;
+                        previous_separator = false;
+                        continue;
+                    }
+                    let keyword = definition.keywords.iter().find_map(|(color, words)| {
+                        words
+                            .iter()
+                            .find(|word| starts(i, word) && is_boundary(i + word.len()))
+                            .map(|word| (*color, word.len()))
+                    });
+                    if let Some((highlight_type, length)) = keyword {
+                        add(highlight_type, length);
+                        i += length;
+                        previous_separator = false;
+                        continue;
+                    }
+                }
+                add(HighlightType::Normal, 1);
+                previous_separator = self.is_separator(render[i] as char);
+                i += 1;
+            }
+            let open = open.or(heredoc);
+            row.highlight = highlight;
+            let changed = row.is_comment != in_comment || row.continuation != open;
+            row.is_comment = in_comment;
+            row.continuation = open;
+            if !changed {
+                break;
+            }
+        }
+    }
+}
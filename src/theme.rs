@@ -0,0 +1,360 @@
+//! Themes give every kind of highlight a style. The bundled ones are in `themes/` and
+//! more can be put in `$XDG_CONFIG_HOME/pound/themes/<name>.toml`, where a theme with the
+//! name of a bundled one takes its place:
+//!
+//! ```toml
+//! base = "default"                      # start from another theme, else unset styles are plain
+//! keyword = { fg = "#d79921", bold = true }
+//! comment = { fg = 245, italic = true } # a colour name, 256-colour index or "#rrggbb"
+//! string = "green"                      # a foreground colour alone
+//! search_match = { fg = "black", bg = "yellow", underline = true }
+//! ```
+//!
+//! The classes are normal, number, string, char, comment, doc_comment, keyword, type,
+//! function, constant, attribute, variable, operator, punctuation, error, strong, emphasis
+//! and search_match.
+//! Styles may also set `reverse`. Colours the terminal can't show are swapped for the
+//! nearest one it can.
+
+use crate::config::{parse_color, parse_toml, Config};
+use crate::palette::ColorDepth;
+use crate::HighlightType;
+use crossterm::queue;
+use crossterm::style::{Attribute, Color, SetAttribute};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use toml::Value;
+
+/// Themes that come with the editor
+const BUNDLED: &[(&str, &str)] = &[
+    ("default", include_str!("../themes/default.toml")),
+    ("light", include_str!("../themes/light.toml")),
+    ("gruvbox", include_str!("../themes/gruvbox.toml")),
+];
+
+/// The names of the kinds of highlight a theme styles
+const CLASSES: &[&str] = &[
+    "normal",
+    "number",
+    "string",
+    "char",
+    "comment",
+    "doc_comment",
+    "keyword",
+    "type",
+    "function",
+    "constant",
+    "attribute",
+    "variable",
+    "operator",
+    "punctuation",
+    "error",
+    "strong",
+    "emphasis",
+    "search_match",
+];
+
+/// How far `base` may lead from one theme to the next, which also stops loops
+const MAX_BASES: usize = 8;
+
+/// How one kind of text is drawn. Colours left unset are the terminal's own
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Reads a style as a colour, which is the foreground, or a table with `fg`, `bg`,
+    /// `bold`, `italic`, `underline` and `reverse`
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => {
+                return parse_color(value)
+                    .map(|color| Self {
+                        fg: Some(color),
+                        ..Self::default()
+                    })
+                    .ok_or_else(|| "is not a colour".to_string())
+            }
+        };
+        let mut style = Self::default();
+        for (key, value) in table {
+            let color = || parse_color(value).ok_or_else(|| format!("{} is not a colour", key));
+            let flag = |value: &Value| {
+                value
+                    .as_bool()
+                    .ok_or_else(|| format!("{} must be true or false", key))
+            };
+            match key.as_str() {
+                "fg" => style.fg = Some(color()?),
+                "bg" => style.bg = Some(color()?),
+                "bold" => style.bold = flag(value)?,
+                "italic" => style.italic = flag(value)?,
+                "underline" => style.underline = flag(value)?,
+                "reverse" => style.reverse = flag(value)?,
+                _ => return Err(format!("{} is not a style setting", key)),
+            }
+        }
+        Ok(style)
+    }
+
+    /// Queues what it takes to go from drawing in `from` to drawing in this style
+    pub fn queue_from(
+        &self,
+        from: &Style,
+        depth: ColorDepth,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        if self.fg != from.fg {
+            depth.queue_color(self.fg.unwrap_or(Color::Reset), false, out)?
+        }
+        if self.bg != from.bg {
+            depth.queue_color(self.bg.unwrap_or(Color::Reset), true, out)?
+        }
+        if self.bold != from.bold {
+            let bold = match self.bold {
+                true => Attribute::Bold,
+                false => Attribute::NormalIntensity,
+            };
+            queue!(out, SetAttribute(bold))?
+        }
+        if self.italic != from.italic {
+            let italic = match self.italic {
+                true => Attribute::Italic,
+                false => Attribute::NoItalic,
+            };
+            queue!(out, SetAttribute(italic))?
+        }
+        if self.underline != from.underline {
+            let underline = match self.underline {
+                true => Attribute::Underlined,
+                false => Attribute::NoUnderline,
+            };
+            queue!(out, SetAttribute(underline))?
+        }
+        if self.reverse != from.reverse {
+            let reverse = match self.reverse {
+                true => Attribute::Reverse,
+                false => Attribute::NoReverse,
+            };
+            queue!(out, SetAttribute(reverse))?
+        }
+        Ok(())
+    }
+}
+
+/// Finds the kind of highlight a theme or syntax file names
+pub fn class(name: &str) -> Option<HighlightType> {
+    let highlight_type = match name {
+        "normal" => HighlightType::Normal,
+        "number" => HighlightType::Number,
+        "string" => HighlightType::String,
+        "char" => HighlightType::CharLiteral,
+        "comment" => HighlightType::Comment,
+        "doc_comment" => HighlightType::DocComment,
+        "keyword" => HighlightType::Keyword,
+        "type" => HighlightType::Type,
+        "function" => HighlightType::Function,
+        "constant" => HighlightType::Constant,
+        "attribute" => HighlightType::Attribute,
+        "variable" => HighlightType::Variable,
+        "operator" => HighlightType::Operator,
+        "punctuation" => HighlightType::Punctuation,
+        "error" => HighlightType::Error,
+        "strong" => HighlightType::Strong,
+        "emphasis" => HighlightType::Emphasis,
+        "search_match" => HighlightType::SearchMatch,
+        _ => return None,
+    };
+    Some(highlight_type)
+}
+
+#[derive(Default)]
+pub struct Theme {
+    pub name: String,
+    normal: Style,
+    number: Style,
+    string: Style,
+    char_literal: Style,
+    comment: Style,
+    doc_comment: Style,
+    keyword: Style,
+    type_name: Style,
+    function: Style,
+    constant: Style,
+    attribute: Style,
+    variable: Style,
+    operator: Style,
+    punctuation: Style,
+    error: Style,
+    strong: Style,
+    emphasis: Style,
+    search_match: Style,
+    /// What the styles have been fitted to
+    pub depth: ColorDepth,
+}
+
+impl Theme {
+    pub fn style(&self, highlight_type: &HighlightType) -> Style {
+        match highlight_type {
+            HighlightType::Normal => self.normal,
+            HighlightType::Number => self.number,
+            HighlightType::String => self.string,
+            HighlightType::CharLiteral => self.char_literal,
+            HighlightType::Comment | HighlightType::MultilineComment => self.comment,
+            HighlightType::DocComment => self.doc_comment,
+            HighlightType::Keyword => self.keyword,
+            HighlightType::Type => self.type_name,
+            HighlightType::Function => self.function,
+            HighlightType::Constant => self.constant,
+            HighlightType::Attribute => self.attribute,
+            HighlightType::Variable => self.variable,
+            HighlightType::Operator => self.operator,
+            HighlightType::Punctuation => self.punctuation,
+            HighlightType::Error => self.error,
+            HighlightType::Strong => self.strong,
+            HighlightType::Emphasis => self.emphasis,
+            HighlightType::SearchMatch => self.search_match,
+            HighlightType::Other(color) => Style {
+                fg: self.depth.fit(*color),
+                ..Style::default()
+            },
+        }
+    }
+
+    fn slot(&mut self, name: &str) -> Option<&mut Style> {
+        let slot = match class(name) {
+            Some(HighlightType::Normal) => &mut self.normal,
+            Some(HighlightType::Number) => &mut self.number,
+            Some(HighlightType::String) => &mut self.string,
+            Some(HighlightType::CharLiteral) => &mut self.char_literal,
+            Some(HighlightType::Comment) => &mut self.comment,
+            Some(HighlightType::DocComment) => &mut self.doc_comment,
+            Some(HighlightType::Keyword) => &mut self.keyword,
+            Some(HighlightType::Type) => &mut self.type_name,
+            Some(HighlightType::Function) => &mut self.function,
+            Some(HighlightType::Constant) => &mut self.constant,
+            Some(HighlightType::Attribute) => &mut self.attribute,
+            Some(HighlightType::Variable) => &mut self.variable,
+            Some(HighlightType::Operator) => &mut self.operator,
+            Some(HighlightType::Punctuation) => &mut self.punctuation,
+            Some(HighlightType::Error) => &mut self.error,
+            Some(HighlightType::Strong) => &mut self.strong,
+            Some(HighlightType::Emphasis) => &mut self.emphasis,
+            Some(HighlightType::SearchMatch) => &mut self.search_match,
+            _ => return None,
+        };
+        Some(slot)
+    }
+
+    /// Gives the class `name` a new style, returning false for an unknown class
+    pub fn set(&mut self, name: &str, style: Style) -> bool {
+        let depth = self.depth;
+        match self.slot(name) {
+            Some(slot) => {
+                *slot = Self::fit_style(style, name, depth);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Swaps the colours for ones the terminal has. Without any, search matches, keywords
+    /// and errors fall back to attributes so that they still stand out
+    fn fit_style(mut style: Style, name: &str, depth: ColorDepth) -> Style {
+        let colored = style.fg.is_some() || style.bg.is_some();
+        style.fg = style.fg.and_then(|color| depth.fit(color));
+        style.bg = style.bg.and_then(|color| depth.fit(color));
+        let plain = !(style.bold || style.italic || style.underline || style.reverse);
+        if depth == ColorDepth::Monochrome && colored && plain {
+            match name {
+                "search_match" => style.reverse = true,
+                "keyword" => style.bold = true,
+                "error" => style.underline = true,
+                _ => {}
+            }
+        }
+        style
+    }
+
+    /// Fits every style to what the terminal can show
+    pub fn fit(&mut self, depth: ColorDepth) {
+        self.depth = depth;
+        for name in CLASSES {
+            let slot = self.slot(name).unwrap();
+            *slot = Self::fit_style(*slot, name, depth)
+        }
+    }
+
+    /// Loads the theme `name`, from the config directory or else the bundled ones, or the
+    /// theme file at `name` when it is a path
+    pub fn load(name: &str) -> Result<Self, String> {
+        Self::load_nested(name, 0, false)
+    }
+
+    /// `bundled` skips the config directory, so that a theme there can be based on the
+    /// bundled one it replaces
+    fn load_nested(name: &str, depth: usize, bundled: bool) -> Result<Self, String> {
+        if depth > MAX_BASES {
+            return Err("has too many bases, or they loop".to_string());
+        }
+        let path = match name.contains('/') {
+            true => Some(Path::new(name).to_path_buf()),
+            false => Config::directory()
+                .map(|directory| directory.join("themes").join(format!("{}.toml", name)))
+                .filter(|path| !bundled && path.exists()),
+        };
+        let text = match &path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|err| format!("can't read {}: {}", path.display(), err))?,
+            None => BUNDLED
+                .iter()
+                .find(|(bundled, _)| *bundled == name)
+                .map(|(_, text)| text.to_string())
+                .ok_or_else(|| format!("no theme called {}", name))?,
+        };
+        let table = parse_toml(&text)?;
+        let mut theme = match table.get("base") {
+            Some(Value::String(base)) => Self::load_nested(base, depth + 1, base == name)?,
+            Some(_) => return Err("base must be a theme name".to_string()),
+            None => Self::default(),
+        };
+        theme.name = match &path {
+            Some(path) => path
+                .file_stem()
+                .map_or(name.to_string(), |stem| stem.to_string_lossy().into()),
+            None => name.to_string(),
+        };
+        for (key, value) in table.iter().filter(|(key, _)| *key != "base") {
+            let style = Style::parse(value).map_err(|problem| format!("{} {}", key, problem))?;
+            if !theme.set(key, style) {
+                return Err(format!("{} is not a highlight class", key));
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Every theme that can be loaded by name, for completion
+    pub fn names() -> Vec<String> {
+        let mut names = BUNDLED
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<String>>();
+        let entries =
+            Config::directory().and_then(|directory| fs::read_dir(directory.join("themes")).ok());
+        names.extend(entries.into_iter().flatten().filter_map(|entry| {
+            let path = entry.ok()?.path();
+            (path.extension()? == "toml").then(|| path.file_stem()?.to_str().map(String::from))?
+        }));
+        names.sort();
+        names.dedup();
+        names
+    }
+}
@@ -0,0 +1,1134 @@
+use crate::*;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+/// Counts beyond this are cut down so that `99999999p` can't exhaust memory
+const MAX_COUNT: usize = 10_000;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    FindForward(char),
+    TillForward(char),
+    FindBackward(char),
+    TillBackward(char),
+}
+
+impl Motion {
+    /// Operators given a linewise motion act on whole lines
+    fn linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    /// Operators given an inclusive motion take the character it lands on as well
+    fn inclusive(self) -> bool {
+        matches!(
+            self,
+            Motion::WordEnd | Motion::FindForward(_) | Motion::TillForward(_)
+        )
+    }
+}
+
+#[derive(Copy, Clone)]
+enum TextObject {
+    Word,
+    Quote(&'static str),
+    Block(&'static str, &'static str),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Copy, Clone)]
+enum Target {
+    Motion(Motion),
+    Object {
+        object: TextObject,
+        around: bool,
+    },
+    /// `dd`, `cc` and `yy`
+    Line,
+    /// What visual mode has selected
+    Selection,
+}
+
+#[derive(Copy, Clone)]
+enum Insert {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Copy, Clone)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    Insert(Insert),
+    Paste { before: bool },
+    ReplaceChar(char),
+    Undo,
+    Visual,
+    Search,
+    CommandLine,
+    Repeat,
+}
+
+impl Action {
+    /// Whether `.` should repeat this
+    fn is_change(self) -> bool {
+        match self {
+            Action::Operate(operator, _) => operator != Operator::Yank,
+            Action::Insert(_) | Action::Paste { .. } | Action::ReplaceChar(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A command with the count typed before it, `None` if there was none
+struct Parsed {
+    count: Option<usize>,
+    action: Action,
+}
+
+enum Parse<T> {
+    Incomplete,
+    Invalid,
+    Done(T),
+}
+
+fn take_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    /* a leading 0 is the motion to the start of the line */
+    let digits = keys
+        .iter()
+        .enumerate()
+        .take_while(|(index, c)| c.is_ascii_digit() && (*index > 0 || **c != '0'))
+        .count();
+    if digits == 0 {
+        return (None, keys);
+    }
+    let count = keys[..digits]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .unwrap_or(MAX_COUNT);
+    (Some(cmp::min(count, MAX_COUNT)), &keys[digits..])
+}
+
+fn parse_motion(keys: &[char]) -> Parse<Motion> {
+    let motion = match *keys {
+        [] | ['g' | 'f' | 't' | 'F' | 'T'] => return Parse::Incomplete,
+        ['h'] => Motion::Left,
+        ['l' | ' '] => Motion::Right,
+        ['k'] => Motion::Up,
+        ['j'] => Motion::Down,
+        ['w'] => Motion::WordForward,
+        ['b'] => Motion::WordBackward,
+        ['e'] => Motion::WordEnd,
+        ['0'] => Motion::LineStart,
+        ['^'] => Motion::FirstNonBlank,
+        ['$'] => Motion::LineEnd,
+        ['g', 'g'] => Motion::FirstLine,
+        ['G'] => Motion::LastLine,
+        ['f', c] => Motion::FindForward(c),
+        ['t', c] => Motion::TillForward(c),
+        ['F', c] => Motion::FindBackward(c),
+        ['T', c] => Motion::TillBackward(c),
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(motion)
+}
+
+fn parse_object(keys: &[char]) -> Parse<TextObject> {
+    let object = match keys {
+        [] => return Parse::Incomplete,
+        ['w'] => TextObject::Word,
+        ['"'] => TextObject::Quote("\""),
+        ['\''] => TextObject::Quote("'"),
+        ['`'] => TextObject::Quote("`"),
+        ['(' | ')' | 'b'] => TextObject::Block("(", ")"),
+        ['{' | '}' | 'B'] => TextObject::Block("{", "}"),
+        ['[' | ']'] => TextObject::Block("[", "]"),
+        ['<' | '>'] => TextObject::Block("<", ">"),
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(object)
+}
+
+/// `keys` without the counts before the command and after its operator
+fn without_counts(keys: &[KeyEvent]) -> Vec<KeyEvent> {
+    /* a 0 that doesn't follow a digit is the motion to the start of the line */
+    let count_length = |keys: &[KeyEvent]| {
+        keys.iter()
+            .enumerate()
+            .take_while(|(index, key)| match key.code {
+                KeyCode::Char(c) => c.is_ascii_digit() && (*index > 0 || c != '0'),
+                _ => false,
+            })
+            .count()
+    };
+    let mut keys = &keys[count_length(keys)..];
+    let mut command = Vec::new();
+    if let Some(operator) = keys
+        .first()
+        .filter(|key| matches!(key.code, KeyCode::Char('d' | 'c' | 'y')))
+    {
+        command.push(*operator);
+        keys = &keys[1..];
+        keys = &keys[count_length(keys)..];
+    }
+    command.extend_from_slice(keys);
+    command
+}
+
+/// Reads a normal mode command: `[count] command`, or `[count] operator [count] target`
+fn parse(keys: &[char]) -> Parse<Parsed> {
+    let (count, keys) = take_count(keys);
+    let action = match *keys {
+        [] | ['r'] => return Parse::Incomplete,
+        ['x'] => Action::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+        ['X'] => Action::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+        ['s'] => Action::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        ['D'] => Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        ['C'] => Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        ['Y'] => Action::Operate(Operator::Yank, Target::Line),
+        ['i'] => Action::Insert(Insert::Before),
+        ['a'] => Action::Insert(Insert::After),
+        ['I'] => Action::Insert(Insert::LineStart),
+        ['A'] => Action::Insert(Insert::LineEnd),
+        ['o'] => Action::Insert(Insert::LineBelow),
+        ['O'] => Action::Insert(Insert::LineAbove),
+        ['p'] => Action::Paste { before: false },
+        ['P'] => Action::Paste { before: true },
+        ['r', c] => Action::ReplaceChar(c),
+        ['u'] => Action::Undo,
+        ['v'] => Action::Visual,
+        ['/'] => Action::Search,
+        [':'] => Action::CommandLine,
+        ['.'] => Action::Repeat,
+        [operator @ ('d' | 'c' | 'y'), ref rest @ ..] => {
+            let operator_kind = match operator {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+            let (motion_count, rest) = take_count(rest);
+            let count = match (count, motion_count) {
+                (None, None) => None,
+                (count, motion_count) => Some(cmp::min(
+                    count.unwrap_or(1) * motion_count.unwrap_or(1),
+                    MAX_COUNT,
+                )),
+            };
+            let target = match rest {
+                [] => return Parse::Incomplete,
+                [c] if *c == operator => Target::Line,
+                [around @ ('i' | 'a'), object @ ..] => match parse_object(object) {
+                    Parse::Incomplete => return Parse::Incomplete,
+                    Parse::Invalid => return Parse::Invalid,
+                    Parse::Done(object) => Target::Object {
+                        object,
+                        around: *around == 'a',
+                    },
+                },
+                motion => match parse_motion(motion) {
+                    Parse::Incomplete => return Parse::Incomplete,
+                    Parse::Invalid => return Parse::Invalid,
+                    Parse::Done(motion) => Target::Motion(motion),
+                },
+            };
+            return Parse::Done(Parsed {
+                count,
+                action: Action::Operate(operator_kind, target),
+            });
+        }
+        _ => match parse_motion(keys) {
+            Parse::Incomplete => return Parse::Incomplete,
+            Parse::Invalid => return Parse::Invalid,
+            Parse::Done(motion) => Action::Move(motion),
+        },
+    };
+    Parse::Done(Parsed { count, action })
+}
+
+/// Visual mode takes motions and applies operators to the selection straight away
+fn parse_visual(keys: &[char]) -> Parse<Parsed> {
+    let (count, keys) = take_count(keys);
+    let action = match keys {
+        ['d' | 'x'] => Action::Operate(Operator::Delete, Target::Selection),
+        ['c' | 's'] => Action::Operate(Operator::Change, Target::Selection),
+        ['y'] => Action::Operate(Operator::Yank, Target::Selection),
+        ['v'] => Action::Visual,
+        _ => match parse_motion(keys) {
+            Parse::Incomplete => return Parse::Incomplete,
+            Parse::Invalid => return Parse::Invalid,
+            Parse::Done(motion) => Action::Move(motion),
+        },
+    };
+    Parse::Done(Parsed { count, action })
+}
+
+/// Word characters, punctuation and blanks; a word motion stops where the class changes
+fn class(grapheme: &str) -> u8 {
+    match grapheme.chars().next() {
+        Some(c) if c.is_whitespace() => 0,
+        Some(c) if c.is_alphanumeric() || c == '_' => 1,
+        _ => 2,
+    }
+}
+
+fn graphemes(editor_rows: &EditorRows, y: usize) -> Vec<&str> {
+    if y < editor_rows.number_of_rows() {
+        editor_rows.get_row(y).graphemes(true).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn first_non_blank(editor_rows: &EditorRows, y: usize) -> Position {
+    let row = graphemes(editor_rows, y);
+    let x = row.iter().take_while(|it| class(it) == 0).count();
+    Position {
+        y,
+        x: cmp::min(x, row.len().saturating_sub(1)),
+    }
+}
+
+fn word_forward(editor_rows: &EditorRows, mut at: Position) -> Position {
+    let mut row = graphemes(editor_rows, at.y);
+    if at.x < row.len() {
+        let start_class = class(row[at.x]);
+        while start_class != 0 && at.x < row.len() && class(row[at.x]) == start_class {
+            at.x += 1
+        }
+    }
+    loop {
+        while at.x < row.len() && class(row[at.x]) == 0 {
+            at.x += 1
+        }
+        if at.x < row.len() || at.y + 1 >= editor_rows.number_of_rows() {
+            return at;
+        }
+        at = Position { y: at.y + 1, x: 0 };
+        row = graphemes(editor_rows, at.y);
+        /* an empty line counts as a word */
+        if row.is_empty() {
+            return at;
+        }
+    }
+}
+
+fn word_backward(editor_rows: &EditorRows, mut at: Position) -> Position {
+    let mut row = graphemes(editor_rows, at.y);
+    at.x = cmp::min(at.x, row.len());
+    loop {
+        if at.x == 0 {
+            if at.y == 0 {
+                return at;
+            }
+            at.y -= 1;
+            row = graphemes(editor_rows, at.y);
+            at.x = row.len();
+            if row.is_empty() {
+                return at;
+            }
+        } else if class(row[at.x - 1]) == 0 {
+            at.x -= 1
+        } else {
+            break;
+        }
+    }
+    let word_class = class(row[at.x - 1]);
+    while at.x > 0 && class(row[at.x - 1]) == word_class {
+        at.x -= 1
+    }
+    at
+}
+
+fn word_end(editor_rows: &EditorRows, mut at: Position) -> Position {
+    let mut row = graphemes(editor_rows, at.y);
+    at.x += 1;
+    loop {
+        while at.x < row.len() && class(row[at.x]) == 0 {
+            at.x += 1
+        }
+        if at.x < row.len() {
+            break;
+        }
+        if at.y + 1 >= editor_rows.number_of_rows() {
+            return Position {
+                y: at.y,
+                x: row.len().saturating_sub(1),
+            };
+        }
+        at = Position { y: at.y + 1, x: 0 };
+        row = graphemes(editor_rows, at.y);
+    }
+    let word_class = class(row[at.x]);
+    while at.x + 1 < row.len() && class(row[at.x + 1]) == word_class {
+        at.x += 1
+    }
+    at
+}
+
+/// The `count`th occurrence of `target` in the cursor row, searching right or left
+fn find_in_row(
+    editor_rows: &EditorRows,
+    at: Position,
+    target: char,
+    count: usize,
+    forward: bool,
+) -> Option<Position> {
+    let row = graphemes(editor_rows, at.y);
+    let target = target.to_string();
+    let matches = |x: &usize| row[*x] == target;
+    let x = if forward {
+        (at.x + 1..row.len()).filter(matches).nth(count - 1)
+    } else {
+        (0..cmp::min(at.x, row.len()))
+            .rev()
+            .filter(matches)
+            .nth(count - 1)
+    };
+    Some(Position { y: at.y, x: x? })
+}
+
+fn motion_target(
+    editor_rows: &EditorRows,
+    from: Position,
+    motion: Motion,
+    count: Option<usize>,
+) -> Option<Position> {
+    let times = count.unwrap_or(1);
+    let last_row = editor_rows.number_of_rows().saturating_sub(1);
+    let row_len = |y: usize| graphemes(editor_rows, y).len();
+    let repeat = |step: fn(&EditorRows, Position) -> Position| {
+        let mut at = from;
+        for _ in 0..times {
+            let next = step(editor_rows, at);
+            if next == at {
+                break;
+            }
+            at = next
+        }
+        at
+    };
+    let target = match motion {
+        Motion::Left => Position {
+            x: from.x.saturating_sub(times),
+            ..from
+        },
+        Motion::Right => Position {
+            x: cmp::min(from.x + times, row_len(from.y)),
+            ..from
+        },
+        Motion::Up => Position {
+            y: from.y.saturating_sub(times),
+            ..from
+        },
+        Motion::Down => Position {
+            y: cmp::min(from.y + times, last_row),
+            ..from
+        },
+        Motion::WordForward => repeat(word_forward),
+        Motion::WordBackward => repeat(word_backward),
+        Motion::WordEnd => repeat(word_end),
+        Motion::LineStart => Position { x: 0, ..from },
+        Motion::FirstNonBlank => first_non_blank(editor_rows, from.y),
+        Motion::LineEnd => {
+            let y = cmp::min(from.y + times - 1, last_row);
+            Position { y, x: row_len(y) }
+        }
+        Motion::FirstLine => {
+            first_non_blank(editor_rows, cmp::min(count.unwrap_or(1) - 1, last_row))
+        }
+        Motion::LastLine => {
+            let y = count.map_or(last_row, |line| cmp::min(line - 1, last_row));
+            first_non_blank(editor_rows, y)
+        }
+        Motion::FindForward(c) => find_in_row(editor_rows, from, c, times, true)?,
+        Motion::TillForward(c) => {
+            let found = find_in_row(editor_rows, from, c, times, true)?;
+            Position {
+                x: found.x - 1,
+                ..found
+            }
+        }
+        Motion::FindBackward(c) => find_in_row(editor_rows, from, c, times, false)?,
+        Motion::TillBackward(c) => {
+            let found = find_in_row(editor_rows, from, c, times, false)?;
+            Position {
+                x: found.x + 1,
+                ..found
+            }
+        }
+    };
+    Some(target)
+}
+
+/// The range of a text object around `at`, as (start, end) with `end` exclusive
+fn text_object(
+    editor_rows: &EditorRows,
+    at: Position,
+    object: TextObject,
+    around: bool,
+) -> Option<(Position, Position)> {
+    let row = graphemes(editor_rows, at.y);
+    let in_row = |start: usize, end: usize| {
+        Some((Position { y: at.y, x: start }, Position { y: at.y, x: end }))
+    };
+    match object {
+        TextObject::Word => {
+            let x = cmp::min(at.x, row.len().checked_sub(1)?);
+            let word_class = class(row[x]);
+            let (mut start, mut end) = (x, x + 1);
+            while start > 0 && class(row[start - 1]) == word_class {
+                start -= 1
+            }
+            while end < row.len() && class(row[end]) == word_class {
+                end += 1
+            }
+            if around && word_class != 0 {
+                let word_end = end;
+                while end < row.len() && class(row[end]) == 0 {
+                    end += 1
+                }
+                if end == word_end {
+                    while start > 0 && class(row[start - 1]) == 0 {
+                        start -= 1
+                    }
+                }
+            }
+            in_row(start, end)
+        }
+        TextObject::Quote(quote) => {
+            let quotes = (0..row.len())
+                .filter(|x| row[*x] == quote && (*x == 0 || row[*x - 1] != "\\"))
+                .collect::<Vec<usize>>();
+            let (open, close) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(_, close)| *close >= at.x)?;
+            if around {
+                in_row(open, close + 1)
+            } else {
+                in_row(open + 1, close)
+            }
+        }
+        TextObject::Block(open, close) => {
+            let start = find_open(editor_rows, at, open, close)?;
+            let end = find_close(editor_rows, start, open, close)?;
+            if around {
+                Some((
+                    start,
+                    Position {
+                        x: end.x + 1,
+                        ..end
+                    },
+                ))
+            } else {
+                Some((
+                    Position {
+                        x: start.x + 1,
+                        ..start
+                    },
+                    end,
+                ))
+            }
+        }
+    }
+}
+
+/// The unmatched `open` before `at`, or at it
+fn find_open(editor_rows: &EditorRows, at: Position, open: &str, close: &str) -> Option<Position> {
+    let mut depth = 0;
+    let mut y = at.y;
+    loop {
+        let row = graphemes(editor_rows, y);
+        let end = if y == at.y {
+            cmp::min(at.x + 1, row.len())
+        } else {
+            row.len()
+        };
+        for x in (0..end).rev() {
+            /* on the closing bracket itself, the block it closes is meant */
+            if row[x] == close && (y, x) != (at.y, at.x) {
+                depth += 1
+            } else if row[x] == open {
+                if depth == 0 {
+                    return Some(Position { y, x });
+                }
+                depth -= 1
+            }
+        }
+        y = y.checked_sub(1)?;
+    }
+}
+
+fn find_close(
+    editor_rows: &EditorRows,
+    start: Position,
+    open: &str,
+    close: &str,
+) -> Option<Position> {
+    let mut depth = 0;
+    for y in start.y..editor_rows.number_of_rows() {
+        let row = graphemes(editor_rows, y);
+        let from = if y == start.y { start.x + 1 } else { 0 };
+        for (x, grapheme) in row.iter().enumerate().skip(from) {
+            if *grapheme == open {
+                depth += 1
+            } else if *grapheme == close {
+                if depth == 0 {
+                    return Some(Position { y, x });
+                }
+                depth -= 1
+            }
+        }
+    }
+    None
+}
+
+/// What an operator acts on: a range of text or whole lines
+enum Span {
+    Chars(Position, Position),
+    Lines(usize, usize),
+}
+
+/// Vim-style modal editing. Insert mode leaves keys to the keymap as usual; normal and
+/// visual mode read plain keys as vim commands
+pub struct Vim {
+    pub mode: Mode,
+    pending: Vec<char>,
+    /// Keys of the change being made, which become `last_change` once it is complete
+    recording: Option<Vec<KeyEvent>>,
+    last_change: Vec<KeyEvent>,
+    /// The clipboard text if it was last yanked or deleted as whole lines
+    linewise: Option<String>,
+    /// Undo stack position and buffer when insert mode was entered
+    insert_start: Option<(usize, usize)>,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            pending: Vec::new(),
+            recording: None,
+            last_change: Vec::new(),
+            linewise: None,
+            insert_start: None,
+        }
+    }
+
+    /// The mode followed by any half typed command, for the status bar
+    pub fn indicator(&self) -> String {
+        let mut indicator = self.mode.name().to_string();
+        if !self.pending.is_empty() {
+            indicator.push(' ');
+            indicator.extend(&self.pending)
+        }
+        indicator
+    }
+}
+
+impl Editor {
+    fn vim(&mut self) -> &mut Vim {
+        self.vim.as_mut().unwrap()
+    }
+
+    pub fn toggle_vim(&mut self) {
+        let message = match self.vim {
+            Some(_) => {
+                if self.vim().mode == Mode::Insert {
+                    self.leave_insert()
+                }
+                self.output.cursor_controller.anchor = None;
+                self.vim = None;
+                "Vim mode off"
+            }
+            None => {
+                self.vim = Some(Vim::new());
+                self.output.cursor_controller.anchor = None;
+                "Vim mode on"
+            }
+        };
+        self.output.status_message.set_message(message.into());
+    }
+
+    /// Brings the status bar and cursor in line with the mode before the screen is drawn:
+    /// outside insert mode the cursor sits on a character, never past the end of the line
+    pub fn update_vim(&mut self) {
+        let vim = match self.vim.as_mut() {
+            Some(vim) => vim,
+            None => {
+                self.output.mode_indicator = None;
+                return;
+            }
+        };
+        if vim.mode == Mode::Visual && self.output.cursor_controller.anchor.is_none() {
+            vim.mode = Mode::Normal
+        }
+        self.output.mode_indicator = Some(vim.indicator());
+        if vim.mode == Mode::Insert {
+            return;
+        }
+        let editor_rows = &self.output.editor_rows;
+        let cursor_controller = &mut self.output.cursor_controller;
+        if editor_rows.number_of_rows() > 0 {
+            cursor_controller.cursor_y =
+                cmp::min(cursor_controller.cursor_y, editor_rows.number_of_rows() - 1);
+            let row_len = editor_rows.get_editor_row(cursor_controller.cursor_y).len();
+            cursor_controller.cursor_x =
+                cmp::min(cursor_controller.cursor_x, row_len.saturating_sub(1));
+        }
+    }
+
+    /// Handles `key` as a vim command. Returns `None` when vim mode is off or the key is
+    /// left to the keymap, as everything but Esc is in insert mode
+    pub fn vim_keypress(&mut self, key: KeyEvent) -> crossterm::Result<Option<bool>> {
+        let vim = match self.vim.as_mut() {
+            Some(vim) => vim,
+            None => return Ok(None),
+        };
+        if vim.mode == Mode::Insert {
+            if let Some(recording) = vim.recording.as_mut() {
+                recording.push(key)
+            }
+            if key.code != KeyCode::Esc {
+                return Ok(None);
+            }
+            self.leave_insert();
+            return Ok(Some(true));
+        }
+        let plain = key.modifiers - KeyModifiers::SHIFT == KeyModifiers::NONE;
+        /* the character `r`, `f`, `F`, `t` and `T` wait for is taken as typed */
+        let argument = matches!(vim.pending.last(), Some('r' | 'f' | 'F' | 't' | 'T'));
+        let c = match key.code {
+            KeyCode::Char(c) if plain => c,
+            KeyCode::Enter if plain && argument => '\n',
+            KeyCode::Backspace | KeyCode::Delete if argument => {
+                vim.pending.clear();
+                return Ok(Some(true));
+            }
+            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                vim.pending.clear();
+                self.output.redo();
+                return Ok(Some(true));
+            }
+            KeyCode::Esc => {
+                vim.pending.clear();
+                if vim.mode == Mode::Visual {
+                    self.set_visual(false)
+                }
+                return Ok(Some(true));
+            }
+            KeyCode::Left | KeyCode::Backspace if plain => 'h',
+            KeyCode::Right if plain => 'l',
+            KeyCode::Up if plain => 'k',
+            KeyCode::Down | KeyCode::Enter if plain => 'j',
+            KeyCode::Home if plain => '0',
+            KeyCode::End if plain => '$',
+            KeyCode::Delete if plain => 'x',
+            KeyCode::Tab if plain => return Ok(Some(true)),
+            _ => {
+                vim.pending.clear();
+                return Ok(None);
+            }
+        };
+        vim.pending.push(c);
+        let parsed = if vim.mode == Mode::Visual {
+            parse_visual(&vim.pending)
+        } else {
+            parse(&vim.pending)
+        };
+        let Parsed { count, action } = match parsed {
+            Parse::Incomplete => return Ok(Some(true)),
+            Parse::Invalid => {
+                vim.pending.clear();
+                return Ok(Some(true));
+            }
+            Parse::Done(parsed) => parsed,
+        };
+        let keys = mem::take(&mut vim.pending);
+        if action.is_change() && vim.mode == Mode::Normal {
+            vim.recording = Some(
+                keys.into_iter()
+                    .map(|c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+                    .collect(),
+            )
+        }
+        let keep_running = self.vim_run(action, count)?;
+        let vim = self.vim();
+        if vim.mode != Mode::Insert {
+            if let Some(recording) = vim.recording.take() {
+                vim.last_change = recording
+            }
+        }
+        Ok(Some(keep_running))
+    }
+
+    /// Returns whether the editor should keep going, as `:q` may have been run
+    fn vim_run(&mut self, action: Action, count: Option<usize>) -> crossterm::Result<bool> {
+        let from = self.output.cursor_controller.position();
+        match action {
+            Action::Move(motion) => {
+                if let Some(to) = motion_target(&self.output.editor_rows, from, motion, count) {
+                    self.output.history.seal();
+                    self.output.cursor_controller.set_position(to)
+                }
+            }
+            Action::Operate(operator, target) => self.operate(operator, target, count),
+            Action::Insert(insert) => self.start_insert(insert),
+            Action::Paste { before } => self.vim_paste(before, count.unwrap_or(1)),
+            Action::ReplaceChar(c) => self.replace_chars(c, count.unwrap_or(1)),
+            Action::Undo => {
+                for _ in 0..count.unwrap_or(1) {
+                    self.output.undo()
+                }
+            }
+            Action::Visual => {
+                let on = self.vim().mode != Mode::Visual;
+                self.set_visual(on)
+            }
+            Action::Search => self.output.find()?,
+            Action::CommandLine => return self.command_line(),
+            /* as in vim, a count replaces the one the change was made with */
+            Action::Repeat => {
+                let mut keys = self.vim().last_change.clone();
+                if let Some(count) = count {
+                    keys = count
+                        .to_string()
+                        .chars()
+                        .map(|c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+                        .chain(without_counts(&keys))
+                        .collect()
+                }
+                for key in &keys {
+                    self.handle_key(*key)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn set_visual(&mut self, on: bool) {
+        let cursor_controller = &mut self.output.cursor_controller;
+        cursor_controller.anchor = on.then(|| cursor_controller.position());
+        self.vim().mode = if on { Mode::Visual } else { Mode::Normal };
+    }
+
+    fn span(&self, target: Target, operator: Operator, count: Option<usize>) -> Option<Span> {
+        let editor_rows = &self.output.editor_rows;
+        let cursor_controller = &self.output.cursor_controller;
+        let from = cursor_controller.position();
+        let last_row = editor_rows.number_of_rows().checked_sub(1)?;
+        let row_len = |y: usize| graphemes(editor_rows, y).len();
+        let span = match target {
+            Target::Line => {
+                Span::Lines(from.y, cmp::min(from.y + count.unwrap_or(1) - 1, last_row))
+            }
+            Target::Selection => {
+                let anchor = cursor_controller.anchor?;
+                let (start, end) = (cmp::min(anchor, from), cmp::max(anchor, from));
+                let end = Position {
+                    x: cmp::min(end.x + 1, row_len(end.y)),
+                    ..end
+                };
+                Span::Chars(start, end)
+            }
+            Target::Object { object, around } => {
+                let (start, end) = text_object(editor_rows, from, object, around)?;
+                Span::Chars(start, end)
+            }
+            Target::Motion(motion) => {
+                /* cw changes to the end of the word, keeping the blanks after it */
+                let on_word = graphemes(editor_rows, from.y)
+                    .get(from.x)
+                    .is_some_and(|it| class(it) != 0);
+                let motion = match motion {
+                    Motion::WordForward if operator == Operator::Change && on_word => {
+                        Motion::WordEnd
+                    }
+                    motion => motion,
+                };
+                let mut to = motion_target(editor_rows, from, motion, count)?;
+                if motion.linewise() {
+                    return Some(Span::Lines(cmp::min(from.y, to.y), cmp::max(from.y, to.y)));
+                }
+                /* dw on the last word of a line stops at the line end */
+                if motion == Motion::WordForward && to.y > from.y && from.x < row_len(from.y) {
+                    to = Position {
+                        y: from.y,
+                        x: row_len(from.y),
+                    }
+                }
+                let (start, mut end) = (cmp::min(from, to), cmp::max(from, to));
+                if motion.inclusive() {
+                    end.x = cmp::min(end.x + 1, row_len(end.y))
+                }
+                Span::Chars(start, end)
+            }
+        };
+        Some(span)
+    }
+
+    fn operate(&mut self, operator: Operator, target: Target, count: Option<usize>) {
+        let selection = matches!(target, Target::Selection);
+        if operator != Operator::Yank && self.output.is_read_only() {
+            self.vim().recording = None;
+            if selection {
+                self.set_visual(false)
+            }
+            return;
+        }
+        let span = self.span(target, operator, count);
+        if selection {
+            self.set_visual(false)
+        }
+        let checkpoint = self.output.history.checkpoint();
+        let span = match span {
+            Some(span) => span,
+            None if operator == Operator::Change
+                && self.output.editor_rows.number_of_rows() == 0 =>
+            {
+                return self.enter_insert(checkpoint)
+            }
+            None => {
+                self.vim().recording = None;
+                return;
+            }
+        };
+        let editor_rows = &self.output.editor_rows;
+        let row_end = |y: usize| Position {
+            y,
+            x: editor_rows.get_editor_row(y).len(),
+        };
+        let (text, start, end) = match span {
+            Span::Chars(start, end) => (editor_rows.get_text(start, end), start, end),
+            Span::Lines(first, last) => {
+                let text = (first..=last)
+                    .map(|y| format!("{}\n", editor_rows.get_row(y)))
+                    .collect();
+                let line_start = |y: usize| Position { y, x: 0 };
+                /* take a line break along, unless the lines are to be replaced */
+                let (start, end) = if operator == Operator::Change {
+                    (line_start(first), row_end(last))
+                } else if last + 1 < editor_rows.number_of_rows() {
+                    (line_start(first), line_start(last + 1))
+                } else if first > 0 {
+                    (row_end(first - 1), row_end(last))
+                } else {
+                    (line_start(first), row_end(last))
+                };
+                (text, start, end)
+            }
+        };
+        /* nothing to take, such as `x` on an empty row, leaves the clipboard alone */
+        if text.is_empty() {
+            if operator == Operator::Change {
+                self.output.cursor_controller.set_position(start);
+                self.enter_insert(checkpoint)
+            }
+            return;
+        }
+        let linewise = matches!(span, Span::Lines(..));
+        self.vim().linewise = linewise.then(|| text.clone());
+        self.output.clipboard = Some(text);
+        let cursor_controller = &mut self.output.cursor_controller;
+        if operator == Operator::Yank {
+            let y = match span {
+                Span::Chars(start, _) => return cursor_controller.set_position(start),
+                Span::Lines(first, _) => first,
+            };
+            cursor_controller.cursor_y = y;
+            return;
+        }
+        cursor_controller.set_position(end);
+        cursor_controller.anchor = Some(start);
+        self.output.delete_selection();
+        self.output.cursor_controller.set_position(start);
+        self.output.history.seal();
+        match span {
+            _ if operator == Operator::Change => self.enter_insert(checkpoint),
+            Span::Lines(first, _) => {
+                let editor_rows = &self.output.editor_rows;
+                let y = cmp::min(first, editor_rows.number_of_rows().saturating_sub(1));
+                let line_start = first_non_blank(editor_rows, y);
+                self.output.cursor_controller.set_position(line_start)
+            }
+            Span::Chars(..) => {}
+        }
+    }
+
+    fn start_insert(&mut self, insert: Insert) {
+        if self.output.is_read_only() {
+            self.vim().recording = None;
+            return;
+        }
+        let checkpoint = self.output.history.checkpoint();
+        let editor_rows = &self.output.editor_rows;
+        let cursor_controller = &mut self.output.cursor_controller;
+        let y = cursor_controller.cursor_y;
+        let row_len = graphemes(editor_rows, y).len();
+        match insert {
+            Insert::Before => {}
+            Insert::After => {
+                cursor_controller.cursor_x = cmp::min(cursor_controller.cursor_x + 1, row_len)
+            }
+            Insert::LineStart => {
+                cursor_controller.cursor_x = graphemes(editor_rows, y)
+                    .iter()
+                    .take_while(|it| class(it) == 0)
+                    .count()
+            }
+            Insert::LineEnd => cursor_controller.cursor_x = row_len,
+            Insert::LineBelow => {
+                cursor_controller.cursor_x = row_len;
+                self.output.insert_newline()
+            }
+            Insert::LineAbove => {
+                cursor_controller.cursor_x = 0;
+                self.output.insert_newline();
+                self.output
+                    .cursor_controller
+                    .set_position(Position { y, x: 0 })
+            }
+        }
+        self.enter_insert(checkpoint)
+    }
+
+    fn enter_insert(&mut self, checkpoint: usize) {
+        let buffer = self.output.current_buffer;
+        let vim = self.vim();
+        vim.mode = Mode::Insert;
+        vim.insert_start = Some((checkpoint, buffer));
+    }
+
+    fn leave_insert(&mut self) {
+        let current_buffer = self.output.current_buffer;
+        let vim = self.vim();
+        vim.mode = Mode::Normal;
+        if let Some(recording) = vim.recording.take() {
+            vim.last_change = recording
+        }
+        /* switching buffers meanwhile leaves the undo stack it was taken from */
+        if let Some((checkpoint, buffer)) = vim.insert_start.take() {
+            if buffer == current_buffer {
+                self.output.history.squash(checkpoint)
+            }
+        }
+        let cursor_controller = &mut self.output.cursor_controller;
+        cursor_controller.cursor_x = cursor_controller.cursor_x.saturating_sub(1);
+    }
+
+    fn vim_paste(&mut self, before: bool, count: usize) {
+        if self.output.is_read_only() {
+            return;
+        }
+        let text = match self.output.clipboard.clone() {
+            Some(text) => text,
+            None => {
+                self.output
+                    .status_message
+                    .set_message("Clipboard is empty".into());
+                return;
+            }
+        };
+        let linewise = self.vim().linewise.as_ref() == Some(&text);
+        let text = text.repeat(count);
+        self.output.history.seal();
+        let editor_rows = &self.output.editor_rows;
+        let cursor = self.output.cursor_controller.position();
+        if !linewise {
+            if !before && cursor.x < graphemes(editor_rows, cursor.y).len() {
+                self.output.cursor_controller.cursor_x += 1
+            }
+            self.output.insert_text(&text);
+            let cursor_controller = &mut self.output.cursor_controller;
+            cursor_controller.cursor_x = cursor_controller.cursor_x.saturating_sub(1);
+            return;
+        }
+        /* whole lines go above or below the cursor line, whatever the column */
+        let number_of_rows = editor_rows.number_of_rows();
+        let line = cmp::min(if before { cursor.y } else { cursor.y + 1 }, number_of_rows);
+        if line < number_of_rows {
+            self.output
+                .cursor_controller
+                .set_position(Position { y: line, x: 0 });
+            self.output.insert_text(&text)
+        } else {
+            let lines = text.strip_suffix('\n').unwrap_or(&text);
+            if number_of_rows == 0 {
+                self.output.insert_text(lines)
+            } else {
+                let last_row = number_of_rows - 1;
+                let end = Position {
+                    y: last_row,
+                    x: editor_rows.get_editor_row(last_row).len(),
+                };
+                self.output.cursor_controller.set_position(end);
+                self.output.insert_text(&format!("\n{}", lines))
+            }
+        }
+        let line_start = first_non_blank(&self.output.editor_rows, line);
+        self.output.cursor_controller.set_position(line_start)
+    }
+
+    fn replace_chars(&mut self, c: char, count: usize) {
+        let at = self.output.cursor_controller.position();
+        let row_len = graphemes(&self.output.editor_rows, at.y).len();
+        if at.x + count > row_len || self.output.is_read_only() {
+            self.vim().recording = None;
+            return;
+        }
+        self.output.history.seal();
+        let cursor_controller = &mut self.output.cursor_controller;
+        cursor_controller.anchor = Some(at);
+        cursor_controller.cursor_x += count;
+        /* like vim, a line break takes the place of all `count` characters */
+        if c == '\n' {
+            self.output.insert_text("\n");
+            self.output.history.seal();
+            return;
+        }
+        self.output.insert_text(&c.to_string().repeat(count));
+        self.output.history.seal();
+        self.output.cursor_controller.cursor_x -= 1
+    }
+}
@@ -0,0 +1,356 @@
+use crate::*;
+
+#[derive(Copy, Clone)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Where a window is drawn. `separator` is set when a vertical bar has to be drawn in the
+/// column just right of `rect`
+pub struct Pane {
+    pub window: usize,
+    pub rect: Rect,
+    pub separator: bool,
+}
+
+/// A view into a buffer. The focused window's cursor lives in `Output::cursor_controller`;
+/// its slot in `Output::windows` is only brought up to date when focus moves away
+#[derive(Copy, Clone)]
+pub struct Window {
+    pub buffer: usize,
+    pub cursor_controller: CursorController,
+}
+
+impl Window {
+    pub fn new(buffer: usize, win_size: (usize, usize)) -> Self {
+        Self {
+            buffer,
+            cursor_controller: CursorController::new(win_size),
+        }
+    }
+}
+
+/// What the gutter left of the text shows. Hybrid numbers the cursor line absolutely and
+/// the others relative to it
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+    Hybrid,
+}
+
+impl LineNumbers {
+    pub fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Hybrid,
+            LineNumbers::Hybrid => LineNumbers::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+
+    /// Room for the widest number plus a space, or nothing if that leaves no room for text
+    pub fn gutter_width(self, number_of_rows: usize, pane_width: usize) -> usize {
+        if self == LineNumbers::Off {
+            return 0;
+        }
+        let width = cmp::max(number_of_rows, 1).to_string().len() + 1;
+        if width < pane_width {
+            width
+        } else {
+            0
+        }
+    }
+
+    pub fn label(self, row: usize, cursor_y: usize) -> usize {
+        match self {
+            LineNumbers::Absolute => row + 1,
+            LineNumbers::Hybrid if row == cursor_y => row + 1,
+            _ => row.abs_diff(cursor_y),
+        }
+    }
+}
+
+/// Everything needed to draw one window
+pub struct View<'a> {
+    pub editor_rows: &'a EditorRows,
+    pub syntax_highlight: Option<&'a dyn SyntaxHighlight>,
+    pub cursor_controller: CursorController,
+    pub dirty: u64,
+    pub buffer: usize,
+    pub line_numbers: LineNumbers,
+    pub theme: &'a Theme,
+}
+
+/// How the screen is divided between windows. A vertical split puts its children side by
+/// side, a horizontal one stacks them
+pub enum Layout {
+    Window(usize),
+    Split {
+        vertical: bool,
+        children: Vec<Layout>,
+    },
+}
+
+impl Layout {
+    pub fn panes(&self, rect: Rect, panes: &mut Vec<Pane>) {
+        let (vertical, children) = match self {
+            Layout::Window(window) => {
+                panes.push(Pane {
+                    window: *window,
+                    rect,
+                    separator: false,
+                });
+                return;
+            }
+            Layout::Split { vertical, children } => (*vertical, children),
+        };
+        let count = children.len();
+        let (mut x, mut y) = (rect.x, rect.y);
+        let available = if vertical {
+            rect.width.saturating_sub(count - 1)
+        } else {
+            rect.height
+        };
+        for (index, child) in children.iter().enumerate() {
+            let last = index == count - 1;
+            let mut size = available / count;
+            if last {
+                size += available % count
+            }
+            let child_rect = if vertical {
+                Rect {
+                    x,
+                    width: size,
+                    ..rect
+                }
+            } else {
+                Rect {
+                    y,
+                    height: size,
+                    ..rect
+                }
+            };
+            let first_pane = panes.len();
+            child.panes(child_rect, panes);
+            if vertical && !last {
+                panes[first_pane..]
+                    .iter_mut()
+                    .filter(|pane| pane.rect.x + pane.rect.width == x + size)
+                    .for_each(|pane| pane.separator = true);
+            }
+            if vertical {
+                x += size + 1
+            } else {
+                y += size
+            }
+        }
+    }
+
+    /// Puts `new` next to `target`, sharing the space `target` had
+    pub fn split(&mut self, target: usize, new: usize, vertical: bool) -> bool {
+        match self {
+            Layout::Window(window) if *window == target => {
+                *self = Layout::Split {
+                    vertical,
+                    children: vec![Layout::Window(target), Layout::Window(new)],
+                };
+                true
+            }
+            Layout::Window(_) => false,
+            Layout::Split {
+                vertical: split_vertical,
+                children,
+            } => {
+                let position = children
+                    .iter()
+                    .position(|child| matches!(child, Layout::Window(window) if *window == target));
+                match position {
+                    Some(index) if *split_vertical == vertical => {
+                        children.insert(index + 1, Layout::Window(new));
+                        true
+                    }
+                    _ => children
+                        .iter_mut()
+                        .any(|child| child.split(target, new, vertical)),
+                }
+            }
+        }
+    }
+
+    /// Drops `target` and shifts the indices above it down, matching `Vec::remove`
+    pub fn remove(&mut self, target: usize) {
+        match self {
+            Layout::Window(window) => {
+                if *window > target {
+                    *window -= 1
+                }
+            }
+            Layout::Split { children, .. } => {
+                children
+                    .retain(|child| !matches!(child, Layout::Window(window) if *window == target));
+                children.iter_mut().for_each(|child| child.remove(target));
+                if children.len() == 1 {
+                    let child = children.remove(0);
+                    *self = child;
+                }
+            }
+        }
+    }
+}
+
+impl Output {
+    pub fn panes(&self) -> Vec<Pane> {
+        let mut panes = Vec::new();
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: self.win_size.0,
+            height: self.win_size.1 + 1,
+        };
+        self.layout.panes(screen, &mut panes);
+        panes
+    }
+
+    /// Fits every window's cursor to its pane; the last row of a pane is its status bar and
+    /// the line number gutter takes its columns from the left
+    pub fn update_window_sizes(&mut self) {
+        for pane in self.panes() {
+            let buffer = self.windows[pane.window].buffer;
+            let number_of_rows =
+                if pane.window == self.current_window || buffer == self.current_buffer {
+                    self.editor_rows.number_of_rows()
+                } else {
+                    self.buffers[buffer].editor_rows.number_of_rows()
+                };
+            let gutter_width = self
+                .line_numbers
+                .gutter_width(number_of_rows, pane.rect.width);
+            let cursor_controller = if pane.window == self.current_window {
+                &mut self.cursor_controller
+            } else {
+                &mut self.windows[pane.window].cursor_controller
+            };
+            cursor_controller.screen_columns = pane.rect.width - gutter_width;
+            cursor_controller.screen_rows = pane.rect.height.saturating_sub(1);
+        }
+    }
+
+    pub fn view(&self, window: usize) -> View<'_> {
+        if window == self.current_window {
+            return View {
+                editor_rows: &self.editor_rows,
+                syntax_highlight: self.syntax_highlight.as_deref(),
+                cursor_controller: self.cursor_controller,
+                dirty: self.dirty,
+                buffer: self.current_buffer,
+                line_numbers: self.line_numbers,
+                theme: &self.config.theme,
+            };
+        }
+        let Window {
+            buffer,
+            mut cursor_controller,
+        } = self.windows[window];
+        let (editor_rows, syntax_highlight, dirty) = if buffer == self.current_buffer {
+            (
+                &self.editor_rows,
+                self.syntax_highlight.as_deref(),
+                self.dirty,
+            )
+        } else {
+            let buffer = &self.buffers[buffer];
+            (
+                &buffer.editor_rows,
+                buffer.syntax_highlight.as_deref(),
+                buffer.dirty,
+            )
+        };
+        /* the buffer may have shrunk while another window was editing it */
+        cursor_controller.clamp(editor_rows);
+        cursor_controller.scroll(editor_rows);
+        View {
+            editor_rows,
+            syntax_highlight,
+            cursor_controller,
+            dirty,
+            buffer,
+            line_numbers: self.line_numbers,
+            theme: &self.config.theme,
+        }
+    }
+
+    pub fn cycle_line_numbers(&mut self) {
+        self.line_numbers = self.line_numbers.next();
+        self.update_window_sizes();
+        self.status_message
+            .set_message(format!("Line numbers: {}", self.line_numbers.name()));
+    }
+
+    pub fn focus_window(&mut self, index: usize) {
+        if index == self.current_window || index >= self.windows.len() {
+            return;
+        }
+        self.windows[self.current_window] = Window {
+            buffer: self.current_buffer,
+            cursor_controller: self.cursor_controller,
+        };
+        self.current_window = index;
+        let window = self.windows[index];
+        self.activate_buffer(window.buffer);
+        self.cursor_controller = window.cursor_controller;
+        self.cursor_controller.clamp(&self.editor_rows);
+        self.cursor_controller.scroll(&self.editor_rows);
+    }
+
+    pub fn split_window(&mut self, vertical: bool) {
+        let new = self.windows.len();
+        self.windows.push(Window {
+            buffer: self.current_buffer,
+            cursor_controller: self.cursor_controller,
+        });
+        self.layout.split(self.current_window, new, vertical);
+        self.update_window_sizes();
+        self.cursor_controller.scroll(&self.editor_rows);
+    }
+
+    /// Moves focus to the next window in screen order
+    pub fn next_window(&mut self) {
+        let panes = self.panes();
+        let position = panes
+            .iter()
+            .position(|pane| pane.window == self.current_window)
+            .unwrap();
+        self.focus_window(panes[(position + 1) % panes.len()].window)
+    }
+
+    pub fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.status_message
+                .set_message("Can't close the only window".into());
+            return;
+        }
+        let closing = self.current_window;
+        self.next_window();
+        self.windows.remove(closing);
+        self.layout.remove(closing);
+        if self.current_window > closing {
+            self.current_window -= 1
+        }
+        self.update_window_sizes();
+        self.cursor_controller.scroll(&self.editor_rows);
+    }
+}